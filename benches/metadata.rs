@@ -0,0 +1,130 @@
+//! Reading metadata of large files: JPEG, TIFF RAW and MOV of hundreds of megabytes.
+//!
+//! Files are sparse, so they don't take the disk space. Run `cargo bench`, the number of files
+//! of each kind is set by `PHOTOMAN_BENCH_FILES` (8 by default).
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs::{self, File},
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use photoman::Manager;
+
+const MB: u64 = 1024 * 1024;
+
+// Allocator which tracks the peak of allocated memory
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// JPEG with EXIF at the start, the image data is after it.
+fn write_jpeg(path: &Path, size: u64) {
+    fs::copy("test_data/images/01.jpg", path).unwrap();
+    File::options().write(true).open(path).unwrap().set_len(size).unwrap();
+}
+
+/// TIFF RAW: IFD0 and Exif IFD at the start, the image data is after them.
+fn write_tiff(path: &Path, size: u64) {
+    let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+        [&tag.to_le_bytes()[..], &kind.to_le_bytes(), &count.to_le_bytes(), &value.to_le_bytes()].concat()
+    };
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    // IFD0: Model, DateTime, Exif IFD pointer
+    tiff.extend(3u16.to_le_bytes());
+    tiff.extend(entry(0x0110, 2, 8, 80));
+    tiff.extend(entry(0x0132, 2, 20, 88));
+    tiff.extend(entry(0x8769, 4, 1, 50));
+    tiff.extend(0u32.to_le_bytes());
+    // Exif IFD: DateTimeOriginal, OffsetTimeOriginal
+    tiff.extend(2u16.to_le_bytes());
+    tiff.extend(entry(0x9003, 2, 20, 108));
+    tiff.extend(entry(0x9011, 2, 7, 128));
+    tiff.extend(0u32.to_le_bytes());
+    tiff.extend(b"ILCE-7\0\0");
+    tiff.extend(b"2020:06:21 22:20:05\0");
+    tiff.extend(b"2020:06:21 22:20:05\0");
+    tiff.extend(b"+03:00\0");
+
+    let mut file = File::create(path).unwrap();
+    file.write_all(&tiff).unwrap();
+    file.set_len(size).unwrap();
+}
+
+/// MOV with a large `mdat` before `moov`, as cameras write them.
+fn write_mov(path: &Path, size: u64) {
+    let mov = fs::read("test_data/images/05.mov").unwrap();
+    // ftyp (20) and wide (8) boxes, mdat (72) is replaced by the large one
+    let (head, moov) = (&mov[..28], &mov[100..]);
+    let mdat_size = size - head.len() as u64 - moov.len() as u64;
+
+    let mut file = File::create(path).unwrap();
+    file.write_all(head).unwrap();
+    file.write_all(&1u32.to_be_bytes()).unwrap();
+    file.write_all(b"mdat").unwrap();
+    file.write_all(&mdat_size.to_be_bytes()).unwrap();
+    file.seek(SeekFrom::Current(mdat_size as i64 - 16)).unwrap();
+    file.write_all(moov).unwrap();
+}
+
+fn bench(name: &str, count: usize, size: u64, write: fn(&Path, u64)) {
+    let dir = std::env::temp_dir().join(format!("photoman_bench_{}", std::process::id())).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("DCIM")).unwrap();
+    for i in 0..count {
+        write(&dir.join("DCIM").join(format!("{:04}.{}", i, name)), size);
+    }
+
+    PEAK.store(ALLOCATED.load(Ordering::Relaxed), Ordering::Relaxed);
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let report = Manager::new().work_dir(&dir).dry_run().no_cache().arrange_files().unwrap();
+    let elapsed = start.elapsed().as_secs_f64();
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+
+    assert_eq!(report.transferred.len(), count, "{:?}", report.unreadable);
+    println!(
+        "{:>4}: {} files of {} MB, {:8.1} files/s, {:10.1} MB/s, peak memory {:.2} MB",
+        name,
+        count,
+        size / MB,
+        count as f64 / elapsed,
+        (count as u64 * size / MB) as f64 / elapsed,
+        peak as f64 / MB as f64
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn main() {
+    let count = std::env::var("PHOTOMAN_BENCH_FILES")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(8);
+    bench("jpg", count, 30 * MB, write_jpeg);
+    bench("dng", count, 120 * MB, write_tiff);
+    bench("mov", count, 4 * 1024 * MB, write_mov);
+}
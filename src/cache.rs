@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use dashmap::DashMap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    error::Error,
+    exifreader::{ExifReader, ReaderKind, Tags},
+    journal::absolute,
+    json,
+    plan::Fingerprint,
+    report::Transferred,
+};
+
+/// Cache of metadata in the work dir, one JSON object per file. Tags of the file are read again
+/// if its size, modification time or inode are changed, or they are read by the other reader.
+pub(crate) const CACHE_NAME: &str = ".photoman-cache.jsonl";
+
+/// The file is the same while it has the same size, modification time and inode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Identity {
+    fingerprint: Fingerprint,
+    device: u64,
+    inode: u64,
+}
+
+impl Identity {
+    fn of(path: &Path) -> io::Result<Identity> {
+        let metadata = fs::metadata(path)?;
+        #[cfg(unix)]
+        let (device, inode) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.dev(), metadata.ino())
+        };
+        #[cfg(not(unix))]
+        let (device, inode) = (0, 0);
+        Ok(Identity {
+            fingerprint: Fingerprint::of_metadata(&metadata)?,
+            device,
+            inode,
+        })
+    }
+}
+
+/// Tags of files by their paths, they are loaded from the work dir and saved back after the run.
+#[derive(Debug)]
+pub(crate) struct MetadataCache {
+    file: PathBuf,
+    entries: DashMap<PathBuf, (Identity, ReaderKind, Tags)>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl MetadataCache {
+    /// Load the cache of the work dir. A broken cache is ignored, all files are read again.
+    pub(crate) fn open(work_dir: &Path) -> MetadataCache {
+        let cache = MetadataCache {
+            file: work_dir.join(CACHE_NAME),
+            entries: DashMap::new(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        };
+        let content = match fs::read_to_string(&cache.file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return cache,
+            Err(e) => {
+                warn!("Can't read the cache {}, error: {}", cache.file.to_string_lossy(), e);
+                return cache;
+            }
+        };
+        for (i, line) in content.lines().enumerate() {
+            match entry_from_json(line) {
+                Ok(Some((path, identity, reader, tags))) => {
+                    cache.entries.insert(path, (identity, reader, tags));
+                }
+                Ok(None) => {} // the entry of older versions, the reader is unknown
+                Err(message) => {
+                    warn!("The cache {} is broken at line {}: {}", cache.file.to_string_lossy(), i + 1, message);
+                    cache.entries.clear();
+                    break;
+                }
+            }
+        }
+        cache
+    }
+
+    // Paths are absolute, so the cache doesn't depend on the current dir
+    fn get(&self, path: &Path, identity: &Identity, reader: ReaderKind) -> Option<Tags> {
+        let tags = self
+            .entries
+            .get(&absolute(path))
+            .filter(|entry| entry.0 == *identity && entry.1 == reader)
+            .map(|entry| entry.2.clone());
+        match tags {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        tags
+    }
+
+    fn insert(&self, path: &Path, identity: Identity, reader: ReaderKind, tags: Tags) {
+        self.entries.insert(absolute(path), (identity, reader, tags));
+    }
+
+    /// Tags of transferred files are kept for their new paths.
+    pub(crate) fn transferred(&self, transferred: &[Transferred]) {
+        transferred.par_iter().for_each(|transferred| {
+            let entry = self.entries.get(&absolute(&transferred.from)).map(|entry| (entry.1, entry.2.clone()));
+            if let (Some((reader, tags)), Ok(identity)) = (entry, Identity::of(&transferred.to)) {
+                self.insert(&transferred.to, identity, reader, tags);
+            }
+        });
+    }
+
+    /// Write the cache, files which don't exist anymore are dropped.
+    pub(crate) fn save(&self) -> Result<(), Error> {
+        self.entries.retain(|path, _| path.exists());
+        debug!(
+            "metadata of {} files is taken from the cache, {} files are read",
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed)
+        );
+
+        // the cache is replaced at once, so it's never written partly
+        let temp = self.file.with_extension("jsonl.photoman-tmp");
+        let mut file = io::BufWriter::new(fs::File::create(&temp)?);
+        for entry in self.entries.iter() {
+            let (identity, reader, tags) = entry.value();
+            writeln!(file, "{}", entry_to_json(entry.key(), identity, *reader, tags)?)?;
+        }
+        file.into_inner().map_err(io::IntoInnerError::into_error)?.sync_data()?;
+        fs::rename(&temp, &self.file)?;
+        Ok(())
+    }
+}
+
+/// Reader which takes tags of unchanged files from the cache.
+pub(crate) struct CachedReader<'a, R> {
+    pub(crate) reader: R,
+    /// Kind of `reader`, tags of other readers aren't taken
+    pub(crate) kind: ReaderKind,
+    pub(crate) cache: Option<&'a MetadataCache>,
+}
+
+impl<R: ExifReader> ExifReader for CachedReader<'_, R> {
+    fn read_tags<P>(&self, file_path: P) -> Result<Tags, Error>
+    where
+        P: AsRef<Path>,
+    {
+        self.read_tags_batch(&[file_path.as_ref().to_path_buf()]).remove(0)
+    }
+
+    fn read_tags_batch(&self, file_paths: &[PathBuf]) -> Vec<Result<Tags, Error>> {
+        let Some(cache) = self.cache else {
+            return self.reader.read_tags_batch(file_paths);
+        };
+
+        let cached: Vec<(Option<Identity>, Option<Tags>)> = file_paths
+            .par_iter()
+            .map(|path| match Identity::of(path) {
+                Ok(identity) => (Some(identity), cache.get(path, &identity, self.kind)),
+                Err(_) => (None, None), // the reader reports the error
+            })
+            .collect();
+        let missed: Vec<PathBuf> = file_paths
+            .iter()
+            .zip(&cached)
+            .filter(|(_, (_, tags))| tags.is_none())
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        let mut read = self.reader.read_tags_batch(&missed).into_iter();
+        file_paths
+            .iter()
+            .zip(cached)
+            .map(|(path, (identity, tags))| match tags {
+                Some(tags) => Ok(tags),
+                None => {
+                    // results are in order of files
+                    let tags = read
+                        .next()
+                        .unwrap_or_else(|| Err(Error::Io(io::Error::other("the file isn't read"))));
+                    if let (Ok(tags), Some(identity)) = (&tags, identity) {
+                        cache.insert(path, identity, self.kind, tags.clone());
+                    }
+                    tags
+                }
+            })
+            .collect()
+    }
+}
+
+// Fields of tags in the cache
+fn tag_fields(tags: &Tags) -> [(&'static str, &Option<String>); 11] {
+    [
+        ("date_time_original", &tags.date_time_original),
+        ("date_time_digitized", &tags.date_time_digitized),
+        ("date_time", &tags.date_time),
+        ("sub_sec_time_original", &tags.sub_sec_time_original),
+        ("sub_sec_time_digitized", &tags.sub_sec_time_digitized),
+        ("sub_sec_time", &tags.sub_sec_time),
+        ("offset_time_original", &tags.offset_time_original),
+        ("offset_time_digitized", &tags.offset_time_digitized),
+        ("offset_time", &tags.offset_time),
+        ("model", &tags.model),
+        ("body_serial_number", &tags.body_serial_number),
+    ]
+}
+
+// Line of the cache, tags which the file doesn't have are omitted
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    #[serde(with = "json::path")]
+    path: PathBuf,
+    size: u64,
+    modified: u64,
+    device: u64,
+    inode: u64,
+    /// Absent in caches of older versions
+    #[serde(default)]
+    reader: Option<ReaderKind>,
+    tags: BTreeMap<String, String>,
+}
+
+fn entry_to_json(path: &Path, identity: &Identity, reader: ReaderKind, tags: &Tags) -> serde_json::Result<String> {
+    let tags = tag_fields(tags)
+        .into_iter()
+        .filter_map(|(name, value)| value.clone().map(|value| (name.to_string(), value)))
+        .collect();
+    serde_json::to_string(&Entry {
+        path: path.to_path_buf(),
+        size: identity.fingerprint.size,
+        modified: identity.fingerprint.modified,
+        device: identity.device,
+        inode: identity.inode,
+        reader: Some(reader),
+        tags,
+    })
+}
+
+fn entry_from_json(line: &str) -> Result<Option<(PathBuf, Identity, ReaderKind, Tags)>, String> {
+    let mut entry: Entry = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let Some(reader) = entry.reader else {
+        return Ok(None);
+    };
+    let identity = Identity {
+        fingerprint: Fingerprint {
+            size: entry.size,
+            modified: entry.modified,
+        },
+        device: entry.device,
+        inode: entry.inode,
+    };
+    let mut tag = |name: &str| entry.tags.remove(name);
+    let tags = Tags {
+        date_time_original: tag("date_time_original"),
+        date_time_digitized: tag("date_time_digitized"),
+        date_time: tag("date_time"),
+        sub_sec_time_original: tag("sub_sec_time_original"),
+        sub_sec_time_digitized: tag("sub_sec_time_digitized"),
+        sub_sec_time: tag("sub_sec_time"),
+        offset_time_original: tag("offset_time_original"),
+        offset_time_digitized: tag("offset_time_digitized"),
+        offset_time: tag("offset_time"),
+        model: tag("model"),
+        body_serial_number: tag("body_serial_number"),
+    };
+    Ok(Some((entry.path, identity, reader, tags)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::test_dir;
+    use crate::exifreader::{create_exif_reader, AnyExifReader};
+
+    // Reader which counts files it reads
+    struct CountingReader {
+        read: AtomicUsize,
+    }
+
+    impl ExifReader for CountingReader {
+        fn read_tags<P>(&self, file_path: P) -> Result<Tags, Error>
+        where
+            P: AsRef<Path>,
+        {
+            self.read.fetch_add(1, Ordering::Relaxed);
+            let reader: AnyExifReader = create_exif_reader(None)?;
+            reader.read_tags(file_path)
+        }
+    }
+
+    #[test]
+    fn changed_files_are_read_again() {
+        let dir = test_dir("cache", "changed_files_are_read_again");
+        let files = vec![dir.join("01.jpg"), dir.join("02.JPG")];
+        fs::copy("test_data/images/01.jpg", &files[0]).unwrap();
+        fs::copy("test_data/suite/IMGP2011/02.JPG", &files[1]).unwrap();
+
+        let read_twice = |expected: usize, kind: ReaderKind| {
+            let cache = MetadataCache::open(&dir);
+            let reader = CachedReader {
+                reader: CountingReader { read: AtomicUsize::new(0) },
+                kind,
+                cache: Some(&cache),
+            };
+            let tags: Vec<Tags> = reader.read_tags_batch(&files).into_iter().map(Result::unwrap).collect();
+            cache.save().unwrap();
+            assert_eq!(reader.reader.read.load(Ordering::Relaxed), expected);
+            tags
+        };
+
+        let tags = read_twice(2, ReaderKind::BuiltIn);
+        assert_eq!(read_twice(0, ReaderKind::BuiltIn), tags);
+        fs::copy("test_data/images/01.jpg", &files[1]).unwrap();
+        assert_eq!(read_twice(1, ReaderKind::BuiltIn)[1], tags[0]);
+
+        // tags of the other reader aren't taken
+        read_twice(2, ReaderKind::ExifTool);
+        read_twice(0, ReaderKind::ExifTool);
+    }
+
+    #[test]
+    fn entries_are_read_back() {
+        let identity = Identity {
+            fingerprint: Fingerprint {
+                size: 1024,
+                modified: 1_592_767_205_000_000_000,
+            },
+            device: 2049,
+            inode: 131_074,
+        };
+        let tags = Tags {
+            date_time_original: Some("2020:06:21 22:20:05".to_string()),
+            model: Some("DSC-RX100M7".to_string()),
+            ..Tags::default()
+        };
+        let path = PathBuf::from("/card/DCIM/Пушкин 01.JPG");
+        let line = entry_to_json(&path, &identity, ReaderKind::ExifTool, &tags).unwrap();
+        assert_eq!(entry_from_json(&line), Ok(Some((path, identity, ReaderKind::ExifTool, tags))));
+        let old = r#"{"path":"/card/01.JPG","size":1,"modified":2,"device":3,"inode":4,"tags":{}}"#;
+        assert_eq!(entry_from_json(old), Ok(None));
+        assert!(entry_from_json(r#"{"path":"/card/01.JPG"}"#).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use chrono::Duration;
+use regex::Regex;
+
+use crate::{
+    error::Error,
+    exifreader::{DateSource, ExifData},
+};
+
+lazy_static! {
+    // model:PENTAX K-5 = +01:13, serial:4012345 = -00:05:30. Digits are ASCII only, \d matches
+    // digits of any script.
+    static ref CORRECTION: Regex =
+        Regex::new(r"^(model|serial)\s*:\s*(.+?)\s*=\s*([+-])([0-9]{1,2}):([0-9]{2})(?::([0-9]{2}))?$").unwrap();
+}
+
+/// Shifts of cameras' clocks, which are added to the time of files taken by the cameras.
+///
+/// Corrections are keyed by EXIF `Model` or `BodySerialNumber`, the serial number is more
+/// specific and wins. The file has one correction per line:
+/// ```text
+/// # the second body was 1h13m late for a whole trip
+/// model:PENTAX K-5 = +01:13
+/// serial:4012345 = -00:05:30
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClockCorrections {
+    by_model: HashMap<String, Duration>,
+    by_serial: HashMap<String, Duration>,
+}
+
+impl ClockCorrections {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ClockCorrections, Error> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Shift time of the file by the correction of its camera. Modification time of the
+    /// file is not taken by the camera's clock and is never shifted.
+    pub(crate) fn apply(&self, exif: ExifData) -> ExifData {
+        if exif.source == DateSource::FileModified {
+            return exif;
+        }
+
+        let by_serial = exif.serial.as_ref().and_then(|s| self.by_serial.get(s));
+        let by_model = exif.camera.as_ref().and_then(|m| self.by_model.get(m));
+        match by_serial.or(by_model) {
+            Some(shift) => ExifData {
+                datetime: exif.datetime + *shift,
+                ..exif
+            },
+            None => exif,
+        }
+    }
+}
+
+impl FromStr for ClockCorrections {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut corrections = ClockCorrections::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || Error::ConfigError {
+                line: i + 1,
+                message: format!(
+                    "expected 'model:<model> = [+-]HH:MM[:SS]' or 'serial:<serial> = [+-]HH:MM[:SS]', got '{}'",
+                    line
+                ),
+            };
+            let c = CORRECTION.captures(line).ok_or_else(invalid)?;
+
+            let number = |i: usize| c.get(i).map_or(Ok(0), |m| m.as_str().parse::<i64>().map_err(|_| invalid()));
+            let shift = Duration::hours(number(4)?) + Duration::minutes(number(5)?) + Duration::seconds(number(6)?);
+            let shift = if &c[3] == "-" { -shift } else { shift };
+
+            let key = c[2].to_string();
+            match &c[1] {
+                "model" => corrections.by_model.insert(key, shift),
+                _ => corrections.by_serial.insert(key, shift),
+            };
+        }
+        Ok(corrections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn exif(camera: Option<&str>, serial: Option<&str>, source: DateSource) -> ExifData {
+        ExifData {
+            datetime: datetime(21, 22, 0),
+            offset: None,
+            source,
+            camera: camera.map(|c| c.to_string()),
+            serial: serial.map(|s| s.to_string()),
+        }
+    }
+
+    fn datetime(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 6, day)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap()
+    }
+
+    const CONFIG: &str = "
+        # comment
+        model:PENTAX K-5 = +01:13
+
+        model:DSC-RX100M7 = +02:00
+        serial:4012345 = -00:05:30
+    ";
+
+    #[test]
+    fn shift_by_model() {
+        let corrections: ClockCorrections = CONFIG.parse().unwrap();
+        let shifted = corrections.apply(exif(Some("PENTAX K-5"), None, DateSource::DateTimeOriginal));
+        assert_eq!(shifted.datetime, datetime(21, 23, 13));
+
+        let shifted = corrections.apply(exif(Some("DSC-RX100M7"), None, DateSource::FileName));
+        assert_eq!(shifted.datetime, datetime(22, 0, 0));
+    }
+
+    #[test]
+    fn serial_wins_over_model() {
+        let corrections: ClockCorrections = CONFIG.parse().unwrap();
+        let shifted = corrections.apply(exif(
+            Some("PENTAX K-5"),
+            Some("4012345"),
+            DateSource::DateTimeOriginal,
+        ));
+        assert_eq!(shifted.datetime, datetime(21, 21, 54) + Duration::seconds(30));
+    }
+
+    #[test]
+    fn unknown_camera_is_not_shifted() {
+        let corrections: ClockCorrections = CONFIG.parse().unwrap();
+        let shifted = corrections.apply(exif(Some("Canon EOS R5"), None, DateSource::DateTimeOriginal));
+        assert_eq!(shifted.datetime, datetime(21, 22, 0));
+    }
+
+    #[test]
+    fn modification_time_is_not_shifted() {
+        let corrections: ClockCorrections = CONFIG.parse().unwrap();
+        let shifted = corrections.apply(exif(Some("PENTAX K-5"), None, DateSource::FileModified));
+        assert_eq!(shifted.datetime, datetime(21, 22, 0));
+    }
+
+    #[test]
+    fn invalid_line() {
+        let corrections = "model:PENTAX K-5 = +01:13\ncamera:X = 01:00".parse::<ClockCorrections>();
+        assert!(matches!(corrections, Err(Error::ConfigError { line: 2, .. })));
+    }
+
+    #[test]
+    fn non_ascii_digits() {
+        let corrections = "model:PENTAX K-5 = +٠١:13".parse::<ClockCorrections>();
+        assert!(matches!(corrections, Err(Error::ConfigError { line: 1, .. })));
+        let corrections = "serial:4012345 = +99999999999999999999:00".parse::<ClockCorrections>();
+        assert!(matches!(corrections, Err(Error::ConfigError { line: 1, .. })));
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+
+use crate::iocommands::same_content;
+
+/// Targets claimed by files in the run, the value is the claiming file. Files of different
+/// folders never get the same target.
+pub(crate) type Claimed = DashMap<PathBuf, PathBuf>;
+
+/// What to do if the folder already has a file with the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The file is left in its folder
+    Skip,
+    /// If the files are identical, the source is deleted (it's kept if files are copied or
+    /// linked), otherwise the file is left in its folder
+    SkipIdentical,
+    /// The file is placed with a numeric suffix, like `IMG_0001_1.JPG`
+    Rename,
+    /// The newer file by modification time is kept
+    KeepNewer,
+    /// The existing file is replaced
+    Overwrite,
+}
+
+/// How a conflict is resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The file is left in its folder
+    Skipped,
+    /// The file is the copy of the existing one, it's deleted unless files are copied
+    Identical,
+    /// The file is placed with a numeric suffix
+    Renamed,
+    /// The existing file is replaced
+    Overwritten,
+}
+
+/// Claim the target for the file `source`. Return the file which has claimed it before.
+pub(crate) fn claim_target(claimed: &Claimed, target: &Path, source: &Path) -> Option<PathBuf> {
+    match claimed.entry(target.to_path_buf()) {
+        Entry::Occupied(entry) => (entry.get() != source).then(|| entry.get().clone()),
+        Entry::Vacant(entry) => {
+            entry.insert(source.to_path_buf());
+            None
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::SkipIdentical => "skip-identical",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::KeepNewer => "keep-newer",
+            ConflictPolicy::Overwrite => "overwrite",
+        })
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "skip-identical" | "identical" => Ok(ConflictPolicy::SkipIdentical),
+            "rename" => Ok(ConflictPolicy::Rename),
+            "keep-newer" | "newer" => Ok(ConflictPolicy::KeepNewer),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            _ => Err(format!(
+                "unknown conflict policy '{}', expected one of: skip, skip-identical, rename, keep-newer, overwrite",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Resolution::Skipped => "skipped",
+            Resolution::Identical => "identical",
+            Resolution::Renamed => "renamed",
+            Resolution::Overwritten => "overwritten",
+        })
+    }
+}
+
+impl ConflictPolicy {
+    /// Resolve the conflict of the file `source` with the existing file `target`.
+    pub(crate) fn resolve(&self, source: &Path, target: &Path) -> io::Result<Resolution> {
+        Ok(match self {
+            ConflictPolicy::Skip => Resolution::Skipped,
+            ConflictPolicy::SkipIdentical => {
+                if same_content(source, target)? {
+                    Resolution::Identical
+                } else {
+                    Resolution::Skipped
+                }
+            }
+            ConflictPolicy::Rename => Resolution::Renamed,
+            ConflictPolicy::KeepNewer => {
+                if fs::metadata(source)?.modified()? > fs::metadata(target)?.modified()? {
+                    Resolution::Overwritten
+                } else {
+                    Resolution::Skipped
+                }
+            }
+            ConflictPolicy::Overwrite => Resolution::Overwritten,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::test_dir;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn skip_identical() {
+        let dir = test_dir("conflicts", "skip_identical");
+        fs::write(dir.join("a.jpg"), "image").unwrap();
+        fs::write(dir.join("b.jpg"), "image").unwrap();
+        fs::write(dir.join("c.jpg"), "other").unwrap();
+
+        let policy = ConflictPolicy::SkipIdentical;
+        assert_eq!(policy.resolve(&dir.join("a.jpg"), &dir.join("b.jpg")).unwrap(), Resolution::Identical);
+        assert_eq!(policy.resolve(&dir.join("a.jpg"), &dir.join("c.jpg")).unwrap(), Resolution::Skipped);
+    }
+
+    #[test]
+    fn keep_newer() {
+        let dir = test_dir("conflicts", "keep_newer");
+        let (old, new) = (dir.join("old.jpg"), dir.join("new.jpg"));
+        fs::write(&old, "old").unwrap();
+        fs::write(&new, "new").unwrap();
+        let file = fs::File::options().write(true).open(&old).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+
+        let policy = ConflictPolicy::KeepNewer;
+        assert_eq!(policy.resolve(&new, &old).unwrap(), Resolution::Overwritten);
+        assert_eq!(policy.resolve(&old, &new).unwrap(), Resolution::Skipped);
+    }
+
+    #[test]
+    fn policies_by_name() {
+        assert_eq!("skip-identical".parse::<ConflictPolicy>(), Ok(ConflictPolicy::SkipIdentical));
+        assert_eq!("Keep-Newer".parse::<ConflictPolicy>(), Ok(ConflictPolicy::KeepNewer));
+        assert!("merge".parse::<ConflictPolicy>().is_err());
+    }
+}
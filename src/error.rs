@@ -1,18 +1,119 @@
 use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
     ExifError(exif::Error),
-    NoFieldError(),
+    /// The file has no date, `tag` lists the sources which are tried
+    NoFieldError { tag: String },
+    /// The file isn't an image or video which metadata can be read from
+    UnsupportedFormat,
+    InvalidDateError { value: String, kind: DateErrorKind },
+    ConfigError { line: usize, message: String },
+    /// The record of the journal at `line` is broken
+    JournalError { path: PathBuf, line: usize, message: String },
+    /// The plan file can't be applied
+    PlanError(String),
+    /// The folder of the file failed to be created
+    FolderNotCreated(PathBuf),
+    /// The copy of the duplicate failed to be transferred, the duplicate isn't removed
+    NotTransferred(PathBuf),
+    /// The target exists, it isn't replaced
+    Conflict { target: PathBuf },
+    /// The operation with the file or folder failed because of `source`
+    Context {
+        operation: Operation,
+        path: PathBuf,
+        source: Box<Error>,
+    },
    // PathNotFile(PathBuf),
    // WalkDirError(),
 }
 
+/// Why a date value can't be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateErrorKind {
+    /// Value is blank, like `"    :  :     :  :  "`
+    Empty,
+    /// Camera didn't know the date, `"0000:00:00 00:00:00"`
+    Zeroed,
+    /// Value has unknown format
+    Malformed,
+    /// Format is right, but the date or time doesn't exist, like `"2020:02:30 12:00:00"`
+    OutOfRange,
+}
+
+/// What is done with the file when the error happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    ReadFolder,
+    ReadMetadata,
+    ParseDate,
+    /// Transfer of the file by the mode, the path is the source
+    Transfer(crate::iocommands::TransferMode),
+    MkDir,
+    RemoveFile,
+    RemoveDir,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::ReadFolder => f.write_str("read folder"),
+            Operation::ReadMetadata => f.write_str("read metadata of"),
+            Operation::ParseDate => f.write_str("parse date of"),
+            Operation::Transfer(mode) => write!(f, "transfer ({})", mode),
+            Operation::MkDir => f.write_str("create folder"),
+            Operation::RemoveFile => f.write_str("remove file"),
+            Operation::RemoveDir => f.write_str("remove folder"),
+        }
+    }
+}
+
+impl fmt::Display for DateErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DateErrorKind::Empty => "empty date",
+            DateErrorKind::Zeroed => "zeroed date",
+            DateErrorKind::Malformed => "malformed date",
+            DateErrorKind::OutOfRange => "date out of range",
+        })
+    }
+}
+
 impl Error {
-    pub(crate) fn log<P : AsRef<std::path::Path>>(&self, path : P) {
-        eprintln!("Can't process [{}], error: {}", path.as_ref().to_string_lossy(), self)
+    /// The error without contexts of operations.
+    pub fn cause(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.cause(),
+            e => e,
+        }
+    }
+
+    /// Kind of the I/O error, if it's the cause.
+    pub fn io_error_kind(&self) -> Option<io::ErrorKind> {
+        match self.cause() {
+            Error::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+/// Add the operation and the path to errors.
+pub(crate) trait Context<T> {
+    fn context(self, operation: Operation, path: &Path) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn context(self, operation: Operation, path: &Path) -> Result<T, Error> {
+        self.map_err(|e| Error::Context {
+            operation,
+            path: path.to_path_buf(),
+            source: Box::new(e.into()),
+        })
     }
 }
 
@@ -28,12 +129,44 @@ impl From<io::Error> for Error {
     }
 }
 
+// Journals, plans and caches are written as JSON, errors of writing are errors of files
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Io(error.into())
+    }
+}
+
+/// The alternate form `{:#}` has causes of the error, like
+/// `can't transfer (move) a.jpg: permission denied`.
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Io(ref io_err) => io_err.fmt(f),
             Error::ExifError(exif_error) => exif_error.fmt(f),
-            Error::NoFieldError() => f.write_str("field not found"),
+            Error::NoFieldError { tag } => write!(f, "field not found: {}", tag),
+            Error::UnsupportedFormat => f.write_str("unsupported format"),
+            Error::InvalidDateError { value, kind } => {
+                write!(f, "{} [{}]", kind, value.escape_default())
+            }
+            Error::ConfigError { line, message } => write!(f, "line {}: {}", line, message),
+            Error::JournalError { path, line, message } => {
+                write!(f, "journal {}, line {}: {}", path.to_string_lossy(), line, message)
+            }
+            Error::PlanError(message) => write!(f, "invalid plan: {}", message),
+            Error::FolderNotCreated(folder) => write!(f, "folder {} isn't created", folder.to_string_lossy()),
+            Error::NotTransferred(copy) => write!(f, "copy {} isn't transferred", copy.to_string_lossy()),
+            Error::Conflict { target } => write!(f, "{} exists", target.to_string_lossy()),
+            Error::Context {
+                operation,
+                path,
+                source,
+            } => {
+                write!(f, "can't {} {}", operation, path.to_string_lossy())?;
+                if f.alternate() {
+                    write!(f, ": {:#}", source)?;
+                }
+                Ok(())
+            }
             // Error::WalkDirError() => f.write_str("cant walk dir"),
             // Error::PathNotFile(p) => f.write_fmt(format_args!(
             //     "expected file, not directory ({})",
@@ -45,6 +178,29 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
+        match self {
+            // their messages are shown as ours
+            Error::Io(io_err) => io_err.source(),
+            Error::ExifError(exif_error) => exif_error.source(),
+            Error::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn context_is_chained() {
+        let result: Result<(), io::Error> = Err(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"));
+        let e = result.context(Operation::MkDir, Path::new("/photos/2020-06-21")).unwrap_err();
+
+        assert_eq!(e.to_string(), "can't create folder /photos/2020-06-21");
+        assert_eq!(format!("{:#}", e), "can't create folder /photos/2020-06-21: permission denied");
+        assert_eq!(e.source().map(|source| source.to_string()), Some("permission denied".to_string()));
+        assert_eq!(e.io_error_kind(), Some(io::ErrorKind::PermissionDenied));
     }
 }
\ No newline at end of file
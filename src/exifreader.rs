@@ -1,24 +1,554 @@
-use std::path::{Path};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use chrono::NaiveDate;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::trace;
 
+use crate::error::{Context, Error, Operation};
 
+use self::dateparser::{is_utc_instant, parse_exif_datetime, parse_offset, parse_subsec};
+use self::exiftoolreader::ExifToolReader;
 use self::rustreader::RustReader;
+pub(crate) use self::isobmff::is_heif;
+pub(crate) use self::videoreader::{VideoContainer, HEADER_SIZE};
+mod dateparser;
+mod exiftoolreader;
+mod heifreader;
+mod isobmff;
+mod jpegreader;
 mod rustreader;
+mod tiffreader;
+mod videoreader;
 
+lazy_static! {
+    // IMG_20200621_123456.jpg, PXL_20200621_123456789.jpg, Screenshot_2020-06-21-12-34-56.png,
+    // IMG-20200621-WA0001.jpg, ...
+    static ref FILE_NAME_DATE: Regex = Regex::new(
+        r"(?:^|\D)((?:19|20)\d{2})[-_.]?(0[1-9]|1[0-2])[-_.]?(0[1-9]|[12]\d|3[01])(?:[-_ T]?(\d{2})[-_.]?(\d{2})[-_.]?(\d{2})\d*)?(?:\D|$)"
+    )
+    .unwrap();
+}
+
+/// Where the date of a file is taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DateSource {
+    /// EXIF `DateTimeOriginal`, when the photo was taken.
+    DateTimeOriginal,
+    /// EXIF `DateTimeDigitized`, when the photo was stored as digital data (differs for scans).
+    DateTimeDigitized,
+    /// EXIF `DateTime`, when the file was changed last time (usually by an editor).
+    DateTime,
+    /// Date in the file name, like `IMG_20200621_123456.jpg` or `Screenshot_2020-06-21.png`.
+    FileName,
+    /// Modification time of the file in the file system.
+    FileModified,
+}
+
+impl DateSource {
+    /// Sources used by default. [DateSource::FileModified] is the last, because the modification
+    /// time of a copied file often is the time of copying.
+    pub const DEFAULT_ORDER: [DateSource; 5] = [
+        DateSource::DateTimeOriginal,
+        DateSource::DateTimeDigitized,
+        DateSource::DateTime,
+        DateSource::FileName,
+        DateSource::FileModified,
+    ];
+}
+
+impl fmt::Display for DateSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DateSource::DateTimeOriginal => "original",
+            DateSource::DateTimeDigitized => "digitized",
+            DateSource::DateTime => "datetime",
+            DateSource::FileName => "filename",
+            DateSource::FileModified => "mtime",
+        })
+    }
+}
+
+impl FromStr for DateSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "original" | "datetimeoriginal" => Ok(DateSource::DateTimeOriginal),
+            "digitized" | "datetimedigitized" => Ok(DateSource::DateTimeDigitized),
+            "datetime" => Ok(DateSource::DateTime),
+            "filename" => Ok(DateSource::FileName),
+            "mtime" | "modified" => Ok(DateSource::FileModified),
+            _ => Err(format!(
+                "unknown date source '{}', expected one of: original, digitized, datetime, filename, mtime",
+                s
+            )),
+        }
+    }
+}
 
 #[derive(Debug,Clone)]
 pub(crate) struct ExifData {
-   pub date: NaiveDate
+   /// Local time of the camera, with fraction of a second if it is known
+   pub datetime: NaiveDateTime,
+   /// Time zone of the camera, if it is known
+   pub offset: Option<FixedOffset>,
+   /// Where the date was taken from
+   pub source: DateSource,
+   /// EXIF `Model`
+   pub camera: Option<String>,
+   /// EXIF `BodySerialNumber`
+   pub serial: Option<String>,
+}
+
+impl ExifData {
+    /// Date and time in the time zone `zone`. If the zone or the camera's time zone is
+    /// unknown, the camera's time is returned as is.
+    pub fn datetime_in(&self, zone: Option<FixedOffset>) -> NaiveDateTime {
+        match (self.offset, zone) {
+            (Some(offset), Some(zone)) => offset
+                .from_local_datetime(&self.datetime)
+                .single()
+                .map_or(self.datetime, |dt| dt.with_timezone(&zone).naive_local()),
+            _ => self.datetime,
+        }
+    }
+}
+
+/// Raw values of date tags, as they are returned by the reader.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Tags {
+    pub date_time_original: Option<String>,
+    pub date_time_digitized: Option<String>,
+    pub date_time: Option<String>,
+    pub sub_sec_time_original: Option<String>,
+    pub sub_sec_time_digitized: Option<String>,
+    pub sub_sec_time: Option<String>,
+    pub offset_time_original: Option<String>,
+    pub offset_time_digitized: Option<String>,
+    pub offset_time: Option<String>,
+    pub model: Option<String>,
+    pub body_serial_number: Option<String>,
+}
+
+impl Tags {
+    /// Return date, subseconds and time zone of the source.
+    fn get(&self, source: DateSource) -> Option<(&str, Option<&str>, Option<&str>)> {
+        let (date, subsec, offset) = match source {
+            DateSource::DateTimeOriginal => (
+                &self.date_time_original,
+                &self.sub_sec_time_original,
+                &self.offset_time_original,
+            ),
+            DateSource::DateTimeDigitized => (
+                &self.date_time_digitized,
+                &self.sub_sec_time_digitized,
+                &self.offset_time_digitized,
+            ),
+            DateSource::DateTime => (&self.date_time, &self.sub_sec_time, &self.offset_time),
+            DateSource::FileName | DateSource::FileModified => return None,
+        };
+
+        date.as_deref()
+            .map(|date| (date, subsec.as_deref(), offset.as_deref()))
+    }
 }
 
 pub(crate) trait ExifReader : Sync {
-    fn read<P>(&self, file_path: P) -> Result<ExifData, crate::error::Error>
+    /// Read date tags of the file.
+    fn read_tags<P>(&self, file_path: P) -> Result<Tags, Error>
     where
         P: AsRef<Path>;
+
+    /// Read tags of many files at once. Result has the same order as `file_paths`.
+    ///
+    /// By default files are read in parallel one by one, readers with expensive requests
+    /// (like an external process) should override it.
+    fn read_tags_batch(&self, file_paths: &[PathBuf]) -> Vec<Result<Tags, Error>> {
+        file_paths.par_iter().map(|path| self.read_tags(path)).collect()
+    }
+
+    /// Read date of the file using [DateSource::DEFAULT_ORDER].
+    #[allow(dead_code)]
+    fn read<P>(&self, file_path: P) -> Result<ExifData, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let tags = self.read_tags(&file_path);
+        resolve_date(file_path, tags, &DateSource::DEFAULT_ORDER)
+    }
+
+    /// Read dates of many files. The first available source from `sources` is used for each
+    /// file. Errors have the path of the file.
+    fn read_batch(&self, file_paths: &[PathBuf], sources: &[DateSource]) -> Vec<Result<ExifData, Error>> {
+        self.read_tags_batch(file_paths)
+            .into_par_iter()
+            .zip(file_paths)
+            .map(|(tags, path)| match resolve_date(path, tags, sources) {
+                Err(e @ Error::InvalidDateError { .. }) => Err(e).context(Operation::ParseDate, path),
+                result => result.context(Operation::ReadMetadata, path),
+            })
+            .collect()
+    }
+}
+
+/// Take date from the first source which has it.
+///
+/// If no one source has the date, return error of parsing the first invalid date, error of
+/// reading tags or [Error::NoFieldError].
+fn resolve_date<P: AsRef<Path>>(
+    file_path: P,
+    tags: Result<Tags, Error>,
+    sources: &[DateSource],
+) -> Result<ExifData, Error> {
+    let mut date_error = None;
+    for source in sources {
+        let datetime = match source {
+            DateSource::FileName => file_path
+                .as_ref()
+                .file_name()
+                .and_then(|name| datetime_from_file_name(&name.to_string_lossy()))
+                .map(|datetime| (datetime, None)),
+            DateSource::FileModified => datetime_from_modified(&file_path),
+            tag => match tags.as_ref().ok().and_then(|tags| tags.get(*tag)) {
+                Some((value, subsec, offset)) => match parse_exif_datetime(value) {
+                    // UTC instants, like dates of videos, are local as the modification time
+                    Ok((datetime, _)) if offset.is_none() && is_utc_instant(value) => {
+                        let (datetime, _) = with_subsec_and_offset(datetime, None, subsec, None);
+                        Some(in_local_zone(Utc.from_utc_datetime(&datetime).with_timezone(&Local)))
+                    }
+                    Ok((datetime, zone)) => Some(with_subsec_and_offset(datetime, zone, subsec, offset)),
+                    Err(e) => {
+                        trace!("{}: {} {}", file_path.as_ref().to_string_lossy(), tag, e);
+                        date_error.get_or_insert(e);
+                        None
+                    }
+                },
+                None => None,
+            },
+        };
+
+        if let Some((datetime, offset)) = datetime {
+            let tag = |get: fn(&Tags) -> &Option<String>| {
+                tags.as_ref()
+                    .ok()
+                    .and_then(|tags| get(tags).as_deref())
+                    .map(|value| value.trim_matches(|c: char| c == '\0' || c.is_whitespace()))
+                    .filter(|value| !value.is_empty())
+                    .map(|value| value.to_string())
+            };
+
+            return Ok(ExifData {
+                datetime,
+                offset,
+                source: *source,
+                camera: tag(|tags| &tags.model),
+                serial: tag(|tags| &tags.body_serial_number),
+            });
+        }
+    }
+
+    match (date_error, tags) {
+        (Some(e), _) => Err(e),
+        (None, Err(e)) => Err(e),
+        (None, Ok(_)) => Err(Error::NoFieldError {
+            tag: sources.iter().map(|source| format!("{:?}", source)).collect::<Vec<_>>().join(", "),
+        }),
+    }
+}
+
+// OffsetTime* and SubSecTime* tags are more precise than the date's own fraction and zone
+fn with_subsec_and_offset(
+    datetime: NaiveDateTime,
+    zone: Option<FixedOffset>,
+    subsec: Option<&str>,
+    offset: Option<&str>,
+) -> (NaiveDateTime, Option<FixedOffset>) {
+    let datetime = match subsec.and_then(parse_subsec) {
+        Some(nanos) if datetime.nanosecond() == 0 => datetime.with_nanosecond(nanos).unwrap_or(datetime),
+        _ => datetime,
+    };
+    (datetime, offset.and_then(parse_offset).or(zone))
+}
+
+/// Date and time (if it is present) in the file name. Time zone is unknown.
+fn datetime_from_file_name(file_name: &str) -> Option<NaiveDateTime> {
+    FILE_NAME_DATE.captures(file_name).and_then(|c| {
+        let date = NaiveDate::from_ymd_opt(
+            c[1].parse().ok()?,
+            c[2].parse().ok()?,
+            c[3].parse().ok()?,
+        )?;
+
+        let time = match (c.get(4), c.get(5), c.get(6)) {
+            (Some(h), Some(m), Some(s)) => NaiveTime::from_hms_opt(
+                h.as_str().parse().ok()?,
+                m.as_str().parse().ok()?,
+                s.as_str().parse().ok()?,
+            ),
+            _ => None,
+        };
+
+        Some(date.and_time(time.unwrap_or(NaiveTime::MIN)))
+    })
+}
+
+fn datetime_from_modified<P: AsRef<Path>>(file_path: P) -> Option<(NaiveDateTime, Option<FixedOffset>)> {
+    let modified = fs::metadata(file_path).and_then(|m| m.modified()).ok()?;
+    Some(in_local_zone(DateTime::<Local>::from(modified)))
+}
+
+// Local time of the instant, the zone is kept, so the date may be computed in other zones
+fn in_local_zone(datetime: DateTime<Local>) -> (NaiveDateTime, Option<FixedOffset>) {
+    (datetime.naive_local(), Some(*datetime.offset()))
 }
 
+/// One of the supported readers, selected at runtime.
+pub(crate) enum AnyExifReader {
+    Rust(RustReader),
+    ExifTool(ExifToolReader),
+}
+
+impl ExifReader for AnyExifReader {
+    fn read_tags<P>(&self, file_path: P) -> Result<Tags, Error>
+    where
+        P: AsRef<Path>,
+    {
+        match self {
+            AnyExifReader::Rust(reader) => reader.read_tags(file_path),
+            AnyExifReader::ExifTool(reader) => reader.read_tags(file_path),
+        }
+    }
+
+    fn read_tags_batch(&self, file_paths: &[PathBuf]) -> Vec<Result<Tags, Error>> {
+        match self {
+            AnyExifReader::Rust(reader) => reader.read_tags_batch(file_paths),
+            AnyExifReader::ExifTool(reader) => reader.read_tags_batch(file_paths),
+        }
+    }
+}
+
+/// Which reader the tags are read by, tags of the same file differ between readers (dates of
+/// videos, tags which the built-in reader doesn't support).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReaderKind {
+    BuiltIn,
+    ExifTool,
+}
+
+impl AnyExifReader {
+    pub(crate) fn kind(&self) -> ReaderKind {
+        match self {
+            AnyExifReader::Rust(_) => ReaderKind::BuiltIn,
+            AnyExifReader::ExifTool(_) => ReaderKind::ExifTool,
+        }
+    }
+}
+
+/// Create reader. If `exiftool` is set, metadata is read by the external exiftool
+/// process, otherwise by the built-in reader.
+pub(crate) fn create_exif_reader(exiftool: Option<&Path>) -> Result<AnyExifReader, Error> {
+    match exiftool {
+        Some(exiftool) => ExifToolReader::new(exiftool).map(AnyExifReader::ExifTool),
+        None => Ok(AnyExifReader::Rust(RustReader {})),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DateErrorKind;
+
+    fn date(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    fn datetime(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> NaiveDateTime {
+        date(y, m, d).unwrap().and_hms_opt(h, min, s).unwrap()
+    }
+
+    fn date_from_file_name(file_name: &str) -> Option<NaiveDate> {
+        datetime_from_file_name(file_name).map(|dt| dt.date())
+    }
+
+    #[test]
+    fn date_from_file_names() {
+        assert_eq!(date_from_file_name("IMG_20200621_123456.jpg"), date(2020, 6, 21));
+        assert_eq!(date_from_file_name("PXL_20211231_235959123.jpg"), date(2021, 12, 31));
+        assert_eq!(date_from_file_name("Screenshot_2022-10-02-12-00-00.png"), date(2022, 10, 2));
+        assert_eq!(date_from_file_name("IMG-20190105-WA0001.jpeg"), date(2019, 1, 5));
+        assert_eq!(date_from_file_name("20200621.jpg"), date(2020, 6, 21));
+    }
+
+    #[test]
+    fn time_from_file_names() {
+        assert_eq!(
+            datetime_from_file_name("IMG_20200621_123456.jpg"),
+            Some(datetime(2020, 6, 21, 12, 34, 56))
+        );
+        assert_eq!(
+            datetime_from_file_name("PXL_20211231_235959123.jpg"),
+            Some(datetime(2021, 12, 31, 23, 59, 59))
+        );
+        assert_eq!(
+            datetime_from_file_name("IMG-20190105-WA0001.jpeg"),
+            Some(datetime(2019, 1, 5, 0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn no_date_in_file_names() {
+        assert_eq!(date_from_file_name("IMGP1011.JPG"), None);
+        assert_eq!(date_from_file_name("DSC_0001.NEF"), None);
+        assert_eq!(date_from_file_name("IMG_20201341_123456.jpg"), None);
+        assert_eq!(date_from_file_name("123202006211.jpg"), None);
+    }
+
+    #[test]
+    fn fallback_to_next_source() {
+        let tags = Tags {
+            date_time_digitized: Some("2020:06:22 10:00:00".to_string()),
+            date_time: Some("2020:06:23 10:00:00".to_string()),
+            ..Tags::default()
+        };
+
+        let exif = resolve_date("IMG_20200621_100000.jpg", Ok(tags), &DateSource::DEFAULT_ORDER).unwrap();
+        assert_eq!(Some(exif.datetime.date()), date(2020, 6, 22));
+        assert_eq!(exif.source, DateSource::DateTimeDigitized);
+    }
+
+    #[test]
+    fn fallback_to_file_name_without_exif() {
+        let exif = resolve_date(
+            "Screenshot_20200621-100000.png",
+            Err(Error::UnsupportedFormat),
+            &DateSource::DEFAULT_ORDER,
+        )
+        .unwrap();
+        assert_eq!(exif.datetime, datetime(2020, 6, 21, 10, 0, 0));
+        assert_eq!(exif.source, DateSource::FileName);
+    }
+
+    #[test]
+    fn fallback_to_modification_time() {
+        let exif = resolve_date(
+            "test_data/images/01.raw",
+            Err(Error::UnsupportedFormat),
+            &[DateSource::DateTimeOriginal, DateSource::FileModified],
+        )
+        .unwrap();
+        assert_eq!(exif.source, DateSource::FileModified);
+        assert!(exif.offset.is_some());
+
+        // it's the last source by default
+        let exif = resolve_date("test_data/images/01.raw", Ok(Tags::default()), &DateSource::DEFAULT_ORDER).unwrap();
+        assert_eq!(exif.source, DateSource::FileModified);
+    }
+
+    #[test]
+    fn fallback_on_invalid_date() {
+        let tags = Tags {
+            date_time_original: Some("0000:00:00 00:00:00".to_string()),
+            date_time: Some("2020:06:23 10:00:00".to_string()),
+            ..Tags::default()
+        };
+
+        let exif = resolve_date("IMGP1011.JPG", Ok(tags), &DateSource::DEFAULT_ORDER).unwrap();
+        assert_eq!(Some(exif.datetime.date()), date(2020, 6, 23));
+        assert_eq!(exif.source, DateSource::DateTime);
+    }
+
+    #[test]
+    fn invalid_date_is_reported() {
+        let tags = Tags {
+            date_time_original: Some("0000:00:00 00:00:00".to_string()),
+            ..Tags::default()
+        };
+
+        let exif = resolve_date("IMGP1011.JPG", Ok(tags), &DateSource::DEFAULT_ORDER);
+        assert!(matches!(
+            exif,
+            Err(Error::InvalidDateError { kind: DateErrorKind::Zeroed, .. })
+        ));
+    }
+
+    #[test]
+    fn no_source_has_date() {
+        let exif = resolve_date("IMGP1011.JPG", Ok(Tags::default()), &DateSource::DEFAULT_ORDER);
+        assert!(matches!(
+            exif,
+            Err(Error::NoFieldError { tag }) if tag == "DateTimeOriginal, DateTimeDigitized, DateTime, FileName, FileModified"
+        ));
+    }
+
+    #[test]
+    fn subseconds_and_offset_of_the_source() {
+        let tags = Tags {
+            date_time_original: Some("2020:06:21 23:59:59".to_string()),
+            sub_sec_time_original: Some("042".to_string()),
+            offset_time_original: Some("+03:00".to_string()),
+            offset_time: Some("+05:00".to_string()),
+            ..Tags::default()
+        };
+
+        let exif = resolve_date("IMGP1011.JPG", Ok(tags), &DateSource::DEFAULT_ORDER).unwrap();
+        assert_eq!(
+            exif.datetime,
+            datetime(2020, 6, 21, 23, 59, 59) + chrono::Duration::milliseconds(42)
+        );
+        assert_eq!(exif.offset, FixedOffset::east_opt(3 * 3600));
+    }
+
+    #[test]
+    fn camera_of_the_file() {
+        let tags = Tags {
+            date_time_original: Some("2020:06:21 23:59:59".to_string()),
+            model: Some("DSC-RX100M7\0".to_string()),
+            body_serial_number: Some("  ".to_string()),
+            ..Tags::default()
+        };
+
+        let exif = resolve_date("IMGP1011.JPG", Ok(tags), &DateSource::DEFAULT_ORDER).unwrap();
+        assert_eq!(exif.camera.as_deref(), Some("DSC-RX100M7"));
+        assert_eq!(exif.serial, None);
+    }
+
+    #[test]
+    fn utc_instant_is_local() {
+        let tags = Tags {
+            date_time_original: Some("2020:06:21 19:00:00Z".to_string()),
+            ..Tags::default()
+        };
+
+        let exif = resolve_date("04.mp4", Ok(tags), &DateSource::DEFAULT_ORDER).unwrap();
+        let local = Utc.with_ymd_and_hms(2020, 6, 21, 19, 0, 0).unwrap().with_timezone(&Local);
+        assert_eq!(exif.datetime, local.naive_local());
+        assert_eq!(exif.offset, Some(*local.offset()));
+        assert_eq!(exif.datetime_in(FixedOffset::east_opt(5 * 3600)), datetime(2020, 6, 22, 0, 0, 0));
+    }
+
+    #[test]
+    fn datetime_in_other_zone() {
+        let exif = ExifData {
+            datetime: datetime(2020, 6, 21, 23, 30, 0),
+            offset: FixedOffset::east_opt(3 * 3600),
+            source: DateSource::DateTimeOriginal,
+            camera: None,
+            serial: None,
+        };
+
+        let utc = FixedOffset::east_opt(0);
+        assert_eq!(exif.datetime_in(utc), datetime(2020, 6, 21, 20, 30, 0));
+        assert_eq!(exif.datetime_in(FixedOffset::east_opt(5 * 3600)).date(), date(2020, 6, 22).unwrap());
+        assert_eq!(exif.datetime_in(None), exif.datetime);
 
-pub(crate) fn create_exif_reader() -> impl ExifReader {
-    RustReader{}
+        let no_offset = ExifData { offset: None, ..exif };
+        assert_eq!(no_offset.datetime_in(utc), no_offset.datetime);
+    }
 }
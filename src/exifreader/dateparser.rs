@@ -0,0 +1,252 @@
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+
+use crate::error::{DateErrorKind, Error};
+
+lazy_static! {
+    // 2020:06:21 12:34:56, 2020-06-21T12:34:56.123+03:00, 20/06/21 12:34, 2020:06:21, ...
+    // Digits are ASCII only, \d matches digits of any script.
+    static ref EXIF_DATETIME: Regex = Regex::new(
+        r"^([0-9]{4}|[0-9]{2})[:\-/.]([0-9]{1,2})[:\-/.]([0-9]{1,2})(?:[ T]+([0-9]{1,2})[:.]([0-9]{1,2})(?:[:.]([0-9]{1,2})(?:[.,]([0-9]{1,9}))?)?)?(.*)$"
+    )
+    .unwrap();
+
+    // what may follow the time: nothing, time zone or an empty time like "  :  :  "
+    static ref TAIL: Regex = Regex::new(r"^(?:\s*|\s*(Z|[+-][0-9]{2}:?[0-9]{2})|[\s:]*)$").unwrap();
+
+    // +03:00, -0530, Z
+    static ref OFFSET: Regex = Regex::new(r"^(?:Z|([+-])([0-9]{2}):?([0-9]{2}))$").unwrap();
+}
+
+/// Two-digit years less than it are 20xx, others are 19xx.
+const CENTURY_PIVOT: i32 = 70;
+
+/// Parse the value of EXIF date tag (`DateTimeOriginal`, `DateTimeDigitized`, `DateTime`).
+///
+/// The standard format is `YYYY:MM:DD HH:MM:SS`, but cameras and editors write many variants:
+/// trailing NULs, `-` or `/` separators, two-digit years, fractional seconds, a time zone or no
+/// time at all. Missing time is midnight.
+///
+/// Return local time and the time zone, if the value has it.
+pub(crate) fn parse_exif_datetime(value: &str) -> Result<(NaiveDateTime, Option<FixedOffset>), Error> {
+    let invalid = |kind| Error::InvalidDateError {
+        value: value.to_string(),
+        kind,
+    };
+
+    let trimmed = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    if !trimmed.chars().any(|c| c.is_ascii_digit()) {
+        return Err(invalid(DateErrorKind::Empty));
+    }
+
+    let c = EXIF_DATETIME
+        .captures(trimmed)
+        .ok_or_else(|| invalid(DateErrorKind::Malformed))?;
+
+    let offset = match TAIL.captures(&c[8]) {
+        Some(tail) => tail.get(1).and_then(|zone| parse_offset(zone.as_str())),
+        None => return Err(invalid(DateErrorKind::Malformed)),
+    };
+
+    let number = |i: usize| -> Result<u32, Error> {
+        c.get(i)
+            .map_or(Ok(0), |m| m.as_str().parse().map_err(|_| invalid(DateErrorKind::Malformed)))
+    };
+
+    let (year, month, day) = (number(1)? as i32, number(2)?, number(3)?);
+    if year == 0 && month == 0 && day == 0 {
+        return Err(invalid(DateErrorKind::Zeroed));
+    }
+
+    let year = if c[1].len() == 2 {
+        if year < CENTURY_PIVOT {
+            2000 + year
+        } else {
+            1900 + year
+        }
+    } else {
+        year
+    };
+
+    let nanos = c.get(7).map_or(Ok(0), |m| {
+        // right-pad fraction to nanoseconds: "5" is 500 ms
+        format!("{:0<9}", m.as_str())
+            .parse::<u32>()
+            .map_err(|_| invalid(DateErrorKind::Malformed))
+    })?;
+
+    let date = NaiveDate::from_ymd_opt(year, month, day)
+        .ok_or_else(|| invalid(DateErrorKind::OutOfRange))?;
+    let time = NaiveTime::from_hms_nano_opt(number(4)?, number(5)?, number(6)?, nanos)
+        .ok_or_else(|| invalid(DateErrorKind::OutOfRange))?;
+
+    Ok((NaiveDateTime::new(date, time), offset))
+}
+
+/// Parse EXIF `OffsetTime*` tag or a time zone of date, like `+03:00`.
+pub(crate) fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let trimmed = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    let c = OFFSET.captures(trimmed)?;
+    if c.get(1).is_none() {
+        return FixedOffset::east_opt(0); // Z
+    }
+
+    let seconds = c[2].parse::<i32>().ok()? * 3600 + c[3].parse::<i32>().ok()? * 60;
+    if &c[1] == "-" {
+        FixedOffset::west_opt(seconds)
+    } else {
+        FixedOffset::east_opt(seconds)
+    }
+}
+
+/// Check that the date is a UTC instant, like `2020:06:21 19:00:00Z`. Such dates have no zone
+/// of the camera, unlike `+00:00`.
+pub(crate) fn is_utc_instant(value: &str) -> bool {
+    value.trim_matches(|c: char| c == '\0' || c.is_whitespace()).ends_with('Z')
+}
+
+/// Parse EXIF `SubSecTime*` tag, it contains digits of the fraction of a second: `"5"` is 500 ms.
+pub(crate) fn parse_subsec(value: &str) -> Option<u32> {
+    let digits = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let digits = &digits[..digits.len().min(9)];
+    format!("{:0<9}", digits).parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, s)
+            .unwrap()
+    }
+
+    fn parse(value: &str) -> NaiveDateTime {
+        parse_exif_datetime(value).unwrap().0
+    }
+
+    fn error_kind(value: &str) -> Option<DateErrorKind> {
+        match parse_exif_datetime(value) {
+            Err(Error::InvalidDateError { kind, .. }) => Some(kind),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn standard() {
+        assert_eq!(parse("2020:06:21 12:34:56"), datetime(2020, 6, 21, 12, 34, 56));
+    }
+
+    #[test]
+    fn dash_separators() {
+        assert_eq!(parse("2020-06-21 12:34:56"), datetime(2020, 6, 21, 12, 34, 56));
+    }
+
+    #[test]
+    fn slash_separators() {
+        assert_eq!(parse("2020/06/21 12:34:56"), datetime(2020, 6, 21, 12, 34, 56));
+    }
+
+    #[test]
+    fn trailing_nuls() {
+        assert_eq!(parse("2020:06:21 12:34:56\0\0\0"), datetime(2020, 6, 21, 12, 34, 56));
+    }
+
+    #[test]
+    fn missing_time() {
+        assert_eq!(parse("2020:06:21"), datetime(2020, 6, 21, 0, 0, 0));
+        assert_eq!(parse("2020:06:21   :  :  "), datetime(2020, 6, 21, 0, 0, 0));
+    }
+
+    #[test]
+    fn missing_seconds() {
+        assert_eq!(parse("2020:06:21 12:34"), datetime(2020, 6, 21, 12, 34, 0));
+    }
+
+    #[test]
+    fn two_digit_year() {
+        assert_eq!(parse("20:06:21 12:34:56"), datetime(2020, 6, 21, 12, 34, 56));
+        assert_eq!(parse("98:06:21 12:34:56"), datetime(1998, 6, 21, 12, 34, 56));
+    }
+
+    #[test]
+    fn fractional_seconds_and_time_zone() {
+        assert_eq!(
+            parse_exif_datetime("2020:06:21 12:34:56.5+03:00").unwrap(),
+            (
+                datetime(2020, 6, 21, 12, 34, 56) + chrono::Duration::milliseconds(500),
+                FixedOffset::east_opt(3 * 3600)
+            )
+        );
+        assert_eq!(
+            parse_exif_datetime("2020-06-21T12:34:56Z").unwrap(),
+            (datetime(2020, 6, 21, 12, 34, 56), FixedOffset::east_opt(0))
+        );
+    }
+
+    #[test]
+    fn without_time_zone() {
+        assert_eq!(parse_exif_datetime("2020:06:21 12:34:56").unwrap().1, None);
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offset("+03:00"), FixedOffset::east_opt(3 * 3600));
+        assert_eq!(parse_offset("-05:30\0"), FixedOffset::west_opt(5 * 3600 + 30 * 60));
+        assert_eq!(parse_offset("+0100"), FixedOffset::east_opt(3600));
+        assert_eq!(parse_offset("Z"), FixedOffset::east_opt(0));
+        assert!(is_utc_instant("2020:06:21 19:00:00Z\0"));
+        assert!(!is_utc_instant("2020:06:21 19:00:00+00:00"));
+        assert_eq!(parse_offset("   :  "), None);
+        assert_eq!(parse_offset("+25:00"), None);
+    }
+
+    #[test]
+    fn subseconds() {
+        assert_eq!(parse_subsec("5"), Some(500_000_000));
+        assert_eq!(parse_subsec("123"), Some(123_000_000));
+        assert_eq!(parse_subsec("04\0"), Some(40_000_000));
+        assert_eq!(parse_subsec(""), None);
+        assert_eq!(parse_subsec("ab"), None);
+    }
+
+    #[test]
+    fn zeroed() {
+        assert_eq!(error_kind("0000:00:00 00:00:00"), Some(DateErrorKind::Zeroed));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(error_kind(""), Some(DateErrorKind::Empty));
+        assert_eq!(error_kind("    :  :     :  :  "), Some(DateErrorKind::Empty));
+        assert_eq!(error_kind("\0\0\0\0"), Some(DateErrorKind::Empty));
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(error_kind("2020:13:01 12:00:00"), Some(DateErrorKind::OutOfRange));
+        assert_eq!(error_kind("2020:02:30 12:00:00"), Some(DateErrorKind::OutOfRange));
+        assert_eq!(error_kind("2020:06:21 25:00:00"), Some(DateErrorKind::OutOfRange));
+        assert_eq!(error_kind("2020:00:00 00:00:00"), Some(DateErrorKind::OutOfRange));
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(error_kind("June 21, 2020"), Some(DateErrorKind::Malformed));
+        assert_eq!(error_kind("2020:06:21 12:34:56 garbage"), Some(DateErrorKind::Malformed));
+    }
+
+    #[test]
+    fn non_ascii_digits() {
+        assert_eq!(error_kind("2020:06:٢١ 12:00:00"), Some(DateErrorKind::Malformed));
+        assert_eq!(error_kind("2020:06:21 12:00:00.١٢"), Some(DateErrorKind::Malformed));
+        assert_eq!(error_kind("2020:06:21 12:00:00 +٠٣:00"), Some(DateErrorKind::Malformed));
+        assert_eq!(parse_offset("+٠٣:00"), None);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::Mutex,
+};
+
+use tracing::{debug, trace};
+
+use crate::error::Error;
+use crate::exifreader::Tags;
+
+use super::ExifReader;
+
+/// How many files are sent to exiftool in one request.
+const BATCH_SIZE: usize = 500;
+
+/// Columns of the answer, exiftool's CreateDate, ModifyDate and SerialNumber are EXIF's
+/// DateTimeDigitized, DateTime and BodySerialNumber (but dates are filled for videos too).
+const TAGS: [&str; 11] = [
+    "-DateTimeOriginal",
+    "-CreateDate",
+    "-ModifyDate",
+    "-SubSecTimeOriginal",
+    "-SubSecTimeDigitized",
+    "-SubSecTime",
+    "-OffsetTimeOriginal",
+    "-OffsetTimeDigitized",
+    "-OffsetTime",
+    "-Model",
+    "-SerialNumber",
+];
+
+/// exiftool prints it in table mode (`-T -f`) when a tag is absent.
+const NO_VALUE: &str = "-";
+
+/// Reads metadata with [exiftool](https://exiftool.org/). It supports many more formats
+/// than the built-in reader (CR3, MKV, proprietary RAWs).
+///
+/// The only one exiftool process is started in `-stay_open` mode and serves all requests.
+pub struct ExifToolReader {
+    process: Mutex<ExifToolProcess>,
+}
+
+struct ExifToolProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    requests_count: usize,
+}
+
+impl ExifToolReader {
+    pub fn new<P: AsRef<Path>>(exiftool: P) -> Result<ExifToolReader, Error> {
+        debug!("start exiftool: {}", exiftool.as_ref().to_string_lossy());
+        let mut child = Command::new(exiftool.as_ref())
+            .args(["-stay_open", "True", "-@", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().ok_or_else(|| closed_pipe("stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| closed_pipe("stdout"))?;
+
+        Ok(ExifToolReader {
+            process: Mutex::new(ExifToolProcess {
+                child,
+                stdin,
+                stdout: BufReader::new(stdout),
+                requests_count: 0,
+            }),
+        })
+    }
+
+    /// Return values of [TAGS] for each file, files unknown for exiftool are absent.
+    fn request(&self, file_paths: &[&str]) -> Result<HashMap<String, Vec<String>>, Error> {
+        let mut process = self.process.lock().unwrap();
+        process.requests_count += 1;
+        let request_id = process.requests_count;
+
+        let mut args = String::new();
+        // QuickTime dates are UTC by the specification, exiftool converts them to local time
+        for arg in ["-T", "-f", "-charset", "filename=utf8", "-api", "QuickTimeUTC", "-SourceFile"] {
+            args.push_str(arg);
+            args.push('\n');
+        }
+        for tag in TAGS {
+            args.push_str(tag);
+            args.push('\n');
+        }
+        for path in file_paths {
+            args.push_str(path);
+            args.push('\n');
+        }
+        args.push_str(&format!("-execute{}\n", request_id));
+
+        process.stdin.write_all(args.as_bytes())?;
+        process.stdin.flush()?;
+
+        let ready = format!("{{ready{}}}", request_id);
+        let mut values = HashMap::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if process.stdout.read_line(&mut line)? == 0 {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "exiftool has exited",
+                )));
+            }
+
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line == ready {
+                break;
+            }
+
+            trace!("exiftool: {}", line);
+            let mut columns = line.split('\t');
+            if let Some(source_file) = columns.next() {
+                values.insert(
+                    source_file.to_string(),
+                    columns.map(|c| c.to_string()).collect(),
+                );
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+impl ExifReader for ExifToolReader {
+    fn read_tags<P>(&self, file_path: P) -> Result<Tags, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        self.read_tags_batch(&[file_path.as_ref().to_path_buf()])
+            .pop()
+            .unwrap() // one file in, one result out
+    }
+
+    fn read_tags_batch(&self, file_paths: &[PathBuf]) -> Vec<Result<Tags, Error>> {
+        let mut result = Vec::with_capacity(file_paths.len());
+        for batch in file_paths.chunks(BATCH_SIZE) {
+            let args: Vec<Result<&str, Error>> = batch.iter().map(|path| argument(path)).collect();
+            let sent: Vec<&str> = args.iter().filter_map(|arg| arg.as_ref().ok().copied()).collect();
+            match self.request(&sent) {
+                // files which exiftool doesn't know aren't answered
+                Ok(values) => result.extend(args.into_iter().map(|arg| {
+                    arg.and_then(|path| values.get(path).map(|columns| tags(columns)).ok_or(Error::UnsupportedFormat))
+                })),
+                Err(e) => {
+                    // the process is broken, the next batches will fail too
+                    let message = e.to_string();
+                    result.extend(args.into_iter().map(|arg| {
+                        arg.and_then(|_| Err(Error::Io(io::Error::other(message.clone()))))
+                    }));
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The path as a line of exiftool's arguments. exiftool gets UTF-8 names (`-charset filename=utf8`)
+/// and answers with the same path, other paths would be sent and matched wrong.
+fn argument(path: &Path) -> Result<&str, Error> {
+    match path.to_str() {
+        Some(path) if !path.contains(['\n', '\r']) => Ok(path),
+        _ => Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "exiftool can't read the file, its path isn't valid UTF-8 or has line breaks",
+        ))),
+    }
+}
+
+fn tags(columns: &[String]) -> Tags {
+    let value = |i: usize| {
+        columns
+            .get(i)
+            .filter(|value| value.as_str() != NO_VALUE)
+            .cloned()
+    };
+
+    Tags {
+        date_time_original: value(0),
+        date_time_digitized: value(1),
+        date_time: value(2),
+        sub_sec_time_original: value(3),
+        sub_sec_time_digitized: value(4),
+        sub_sec_time: value(5),
+        offset_time_original: value(6),
+        offset_time_digitized: value(7),
+        offset_time: value(8),
+        model: value(9),
+        body_serial_number: value(10),
+    }
+}
+
+fn closed_pipe(name: &str) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        format!("exiftool's {} is not available", name),
+    ))
+}
+
+impl Drop for ExifToolProcess {
+    fn drop(&mut self) {
+        let stopped = self
+            .stdin
+            .write_all(b"-stay_open\nFalse\n")
+            .and_then(|_| self.stdin.flush());
+        if stopped.is_err() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,265 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::error::Error;
+
+use super::isobmff::{be_u32, for_each_box, invalid_data, read_box_content};
+
+// Item information and locations are small, bigger boxes are broken
+const ITEMS_BOX_LIMIT: u64 = 1024 * 1024;
+
+// EXIF is limited by 64 KB in JPEG, HEIF has no limit, but bigger one is broken
+const EXIF_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// Location of the item's data, see `iloc` box.
+#[derive(Debug, Default)]
+struct ItemLocation {
+    item_id: u32,
+    /// 0 - offsets in the file, 1 - offsets in `idat` box
+    construction_method: u8,
+    base_offset: u64,
+    extents: Vec<(u64, u64)>,
+}
+
+/// Read EXIF of HEIF image (HEIC, AVIF). `reader` should be at the start of the file.
+///
+/// EXIF is stored as an item with type `Exif`, `iinf` box has types of items and `iloc` box has
+/// their locations. Return TIFF data, which is accepted by [exif::Reader::read_raw].
+pub(crate) fn read_heif_exif<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+
+    let mut exif_item = None;
+    let mut locations = Vec::new();
+    let mut idat = None;
+    for_each_box(reader, 0, file_end, |reader, header| {
+        if &header.kind == b"meta" {
+            // meta is a full box, 4 bytes of version and flags
+            for_each_box(reader, header.start + 4, header.end, |reader, header| {
+                match &header.kind {
+                    b"iinf" => exif_item = find_exif_item(&read_box_content(reader, header, ITEMS_BOX_LIMIT)?)?,
+                    b"iloc" => locations = parse_locations(&read_box_content(reader, header, ITEMS_BOX_LIMIT)?)?,
+                    b"idat" => idat = Some(header.start),
+                    _ => {}
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })?;
+
+    let exif_item = exif_item.ok_or(exif::Error::NotFound("HEIF"))?;
+    let location = locations
+        .into_iter()
+        .find(|location| location.item_id == exif_item)
+        .ok_or_else(|| invalid_data("location of EXIF item is unknown"))?;
+
+    // offsets and lengths are from the file, huge ones are broken
+    let out_of_file = || invalid_data("EXIF item is out of the file");
+    let base = match location.construction_method {
+        0 => location.base_offset,
+        1 => idat
+            .ok_or_else(|| invalid_data("no idat box"))?
+            .checked_add(location.base_offset)
+            .ok_or_else(out_of_file)?,
+        _ => return Err(invalid_data("unsupported construction method of EXIF item")),
+    };
+
+    let mut data = Vec::new();
+    for (offset, length) in location.extents {
+        let start = base.checked_add(offset).ok_or_else(out_of_file)?;
+        // zero length is "to the end of the file"
+        let length = if length == 0 { file_end.saturating_sub(start) } else { length };
+        if (data.len() as u64).checked_add(length).is_none_or(|size| size > EXIF_LIMIT) {
+            return Err(invalid_data("EXIF item is too big"));
+        }
+        reader.seek(SeekFrom::Start(start))?;
+        reader.by_ref().take(length).read_to_end(&mut data)?;
+    }
+
+    // the item starts with offset of TIFF header, Apple puts "Exif\0\0" before it
+    if data.len() < 4 {
+        return Err(invalid_data("EXIF item is truncated"));
+    }
+    let tiff_start = 4 + be_u32(&data[0..4]) as usize;
+    if tiff_start > data.len() {
+        return Err(invalid_data("EXIF item is truncated"));
+    }
+    Ok(data.split_off(tiff_start))
+}
+
+/// Reads big-endian numbers of different sizes from the box's content.
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Cursor<'a> {
+        Cursor { data, position: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + n)
+            .ok_or_else(|| invalid_data("box is truncated"))?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    /// Read number of `size` bytes: 0, 1, 2, 4 or 8.
+    fn uint(&mut self, size: usize) -> Result<u64, Error> {
+        Ok(self.bytes(size)?.iter().fold(0u64, |value, byte| value << 8 | *byte as u64))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+}
+
+/// Return ID of the first item with type `Exif` in `iinf` box.
+fn find_exif_item(iinf: &[u8]) -> Result<Option<u32>, Error> {
+    let mut cursor = Cursor::new(iinf);
+    let version = cursor.uint(1)?;
+    cursor.uint(3)?; // flags
+    cursor.uint(if version == 0 { 2 } else { 4 })?; // entry count, entries are boxes up to the end
+
+    while !cursor.is_empty() {
+        let size = cursor.uint(4)? as usize;
+        let kind = cursor.bytes(4)?;
+        if size < 8 {
+            return Err(invalid_data("infe box is broken"));
+        }
+        let mut infe = Cursor::new(cursor.bytes(size - 8)?);
+        if kind != b"infe" {
+            continue;
+        }
+
+        // versions 0 and 1 have no item type, they are not used for EXIF
+        let version = infe.uint(1)?;
+        infe.uint(3)?;
+        let item_id = match version {
+            2 => infe.uint(2)?,
+            3 => infe.uint(4)?,
+            _ => continue,
+        };
+        infe.uint(2)?; // protection index
+        if infe.bytes(4)? == b"Exif" {
+            return Ok(Some(item_id as u32));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_locations(iloc: &[u8]) -> Result<Vec<ItemLocation>, Error> {
+    let mut cursor = Cursor::new(iloc);
+    let version = cursor.uint(1)?;
+    cursor.uint(3)?;
+    if version > 2 {
+        return Err(invalid_data("unsupported version of iloc box"));
+    }
+
+    let sizes = cursor.uint(2)?;
+    let offset_size = (sizes >> 12 & 0xf) as usize;
+    let length_size = (sizes >> 8 & 0xf) as usize;
+    let base_offset_size = (sizes >> 4 & 0xf) as usize;
+    let index_size = if version > 0 { (sizes & 0xf) as usize } else { 0 };
+
+    let item_count = cursor.uint(if version < 2 { 2 } else { 4 })?;
+    let mut locations = Vec::new();
+    for _ in 0..item_count {
+        let mut location = ItemLocation {
+            item_id: cursor.uint(if version < 2 { 2 } else { 4 })? as u32,
+            ..ItemLocation::default()
+        };
+        if version > 0 {
+            location.construction_method = (cursor.uint(2)? & 0xf) as u8;
+        }
+        cursor.uint(2)?; // data reference index
+        location.base_offset = cursor.uint(base_offset_size)?;
+
+        let extent_count = cursor.uint(2)?;
+        for _ in 0..extent_count {
+            cursor.uint(index_size)?;
+            let offset = cursor.uint(offset_size)?;
+            let length = cursor.uint(length_size)?;
+            location.extents.push((offset, length));
+        }
+        locations.push(location);
+    }
+    Ok(locations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::File, io::BufReader};
+
+    fn read(path: &str) -> exif::Exif {
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        let tiff = read_heif_exif(&mut reader).unwrap();
+        exif::Reader::new().read_raw(tiff).unwrap()
+    }
+
+    fn field(exif: &exif::Exif, tag: exif::Tag) -> String {
+        exif.get_field(tag, exif::In::PRIMARY)
+            .unwrap()
+            .display_value()
+            .to_string()
+    }
+
+    #[test]
+    fn heic() {
+        // iloc version 0, EXIF in mdat with "Exif\0\0" before TIFF
+        let exif = read("test_data/images/08.heic");
+        assert_eq!(field(&exif, exif::Tag::DateTimeOriginal), "2021-07-15 10:00:00");
+        assert_eq!(field(&exif, exif::Tag::Model), "\"iPhone 12\"");
+    }
+
+    #[test]
+    fn avif() {
+        // iloc version 1, EXIF in idat
+        let exif = read("test_data/images/09.avif");
+        assert_eq!(field(&exif, exif::Tag::DateTimeOriginal), "2021-08-01 09:30:00");
+    }
+
+    fn boxed(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        [&(content.len() as u32 + 8).to_be_bytes()[..], kind, content].concat()
+    }
+
+    // HEIF with the EXIF item at `base` with extents of 8 bytes offsets and lengths
+    fn heif(base: u64, extents: &[(u64, u64)]) -> Vec<u8> {
+        let infe = boxed(b"infe", &[&[2, 0, 0, 0, 0, 1, 0, 0][..], b"Exif\0"].concat());
+        let iinf = boxed(b"iinf", &[&[0, 0, 0, 0, 0, 1][..], &infe].concat());
+        let mut iloc = vec![0, 0, 0, 0, 0x88, 0x80, 0, 1, 0, 1, 0, 0];
+        iloc.extend(base.to_be_bytes());
+        iloc.extend((extents.len() as u16).to_be_bytes());
+        for (offset, length) in extents {
+            iloc.extend(offset.to_be_bytes());
+            iloc.extend(length.to_be_bytes());
+        }
+        let meta = boxed(b"meta", &[&[0, 0, 0, 0][..], &iinf, &boxed(b"iloc", &iloc)].concat());
+        [boxed(b"ftyp", b"heic\0\0\0\0mif1"), meta].concat()
+    }
+
+    #[test]
+    fn huge_locations() {
+        let is_invalid = |file: Vec<u8>| {
+            matches!(
+                read_heif_exif(&mut std::io::Cursor::new(file)),
+                Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData
+            )
+        };
+        assert!(is_invalid(heif(u64::MAX, &[(1, 10)])));
+        assert!(is_invalid(heif(u64::MAX, &[(1, 0)])));
+        assert!(is_invalid(heif(0, &[(0, 10), (0, u64::MAX)])));
+    }
+
+    #[test]
+    fn no_exif() {
+        let mut reader = BufReader::new(File::open("test_data/images/04.mp4").unwrap());
+        assert!(matches!(
+            read_heif_exif(&mut reader),
+            Err(Error::ExifError(exif::Error::NotFound(_)))
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::error::Error;
+
+// Boxes of ISO base media file format, the container of MP4, MOV, HEIF and AVIF
+
+/// Brands of still images: HEIF, HEIC and AVIF. Files with other brands are videos.
+const HEIF_BRANDS: [&[u8; 4]; 10] = [
+    b"mif1", b"mif2", b"msf1", b"heic", b"heix", b"heim", b"heis", b"hevc", b"avif", b"avis",
+];
+
+/// Check the major and compatible brands in `ftyp` box at the start of the file.
+pub(crate) fn is_heif(header: &[u8]) -> bool {
+    if header.len() < 16 || &header[4..8] != b"ftyp" {
+        return false;
+    }
+
+    let ftyp_end = (be_u32(&header[0..4]) as usize).min(header.len());
+    let major = std::iter::once(&header[8..12]);
+    // minor version is between major and compatible brands
+    let compatible = header.get(16..ftyp_end).unwrap_or_default().chunks_exact(4);
+    major.chain(compatible).any(|brand| HEIF_BRANDS.iter().any(|heif| brand == *heif))
+}
+
+pub(crate) struct BoxHeader {
+    pub kind: [u8; 4],
+    /// Offset of the box's content
+    pub start: u64,
+    /// Offset after the box
+    pub end: u64,
+}
+
+impl BoxHeader {
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message.to_string()))
+}
+
+/// Read header of the box at the current position. Return None at the end of the parent.
+pub(crate) fn read_box_header<R: Read + Seek>(reader: &mut R, parent_end: u64) -> Result<Option<BoxHeader>, Error> {
+    let position = reader.stream_position()?;
+    if position.checked_add(8).is_none_or(|header_end| header_end > parent_end) {
+        return Ok(None);
+    }
+
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let kind = [header[4], header[5], header[6], header[7]];
+
+    // sizes are from the file, a huge one is broken
+    let out_of_parent = || invalid_data("box is out of the parent");
+    let (start, end) = match size {
+        0 => (position + 8, parent_end), // box lasts to the end of the parent
+        1 => {
+            let mut large_size = [0u8; 8];
+            reader.read_exact(&mut large_size)?;
+            let end = position.checked_add(u64::from_be_bytes(large_size)).ok_or_else(out_of_parent)?;
+            (position + 16, end)
+        }
+        size => (position + 8, position.checked_add(size).ok_or_else(out_of_parent)?),
+    };
+
+    if end < start || end > parent_end {
+        return Err(out_of_parent());
+    }
+
+    Ok(Some(BoxHeader { kind, start, end }))
+}
+
+/// Call `f` for each child box in `start..end`. `f` may read the box, the reader is moved
+/// to the next box after it.
+pub(crate) fn for_each_box<R, F>(reader: &mut R, start: u64, end: u64, mut f: F) -> Result<(), Error>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, &BoxHeader) -> Result<(), Error>,
+{
+    reader.seek(SeekFrom::Start(start))?;
+    while let Some(header) = read_box_header(reader, end)? {
+        f(reader, &header)?;
+        reader.seek(SeekFrom::Start(header.end))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_box_content<R: Read + Seek>(reader: &mut R, header: &BoxHeader, limit: u64) -> Result<Vec<u8>, Error> {
+    let size = header.size().min(limit);
+    let mut content = vec![0u8; size as usize];
+    reader.seek(SeekFrom::Start(header.start))?;
+    reader.read_exact(&mut content)?;
+    Ok(content)
+}
+
+pub(crate) fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub(crate) fn be_u64(bytes: &[u8]) -> u64 {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(&bytes[0..8]);
+    u64::from_be_bytes(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn huge_box_size() {
+        // the box with 64-bit size after 8 bytes of another box
+        let mut file = vec![0, 0, 0, 8, b'f', b'r', b'e', b'e', 0, 0, 0, 1, b'm', b'd', b'a', b't'];
+        file.extend(u64::MAX.to_be_bytes());
+        let mut reader = Cursor::new(file);
+        reader.set_position(8);
+        assert!(matches!(
+            read_box_header(&mut reader, u64::MAX),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+        assert!(for_each_box(&mut reader, 0, u64::MAX, |_, _| Ok(())).is_err());
+    }
+}
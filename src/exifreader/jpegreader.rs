@@ -0,0 +1,89 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::error::Error;
+
+use super::isobmff::invalid_data;
+
+const MARKER: u8 = 0xff;
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+const APP1: u8 = 0xe1;
+const EXIF_ID: &[u8] = b"Exif\0\0";
+
+/// Check the start of image marker.
+pub(crate) fn is_jpeg(header: &[u8]) -> bool {
+    header.starts_with(&[MARKER, SOI])
+}
+
+/// Read EXIF of JPEG. `reader` should be at the start of the file.
+///
+/// Segments before the image data are skipped by their lengths, only the EXIF segment (up to
+/// 64 KB) is read. Return TIFF data, which is accepted by [exif::Reader::read_raw].
+pub(crate) fn read_jpeg_exif<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    reader.seek(SeekFrom::Start(2))?;
+    loop {
+        let mut marker = [0u8; 2];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != MARKER {
+            return Err(invalid_data("JPEG segment has no marker"));
+        }
+        match marker[1] {
+            // fill bytes before the marker
+            MARKER => {
+                reader.seek(SeekFrom::Current(-1))?;
+                continue;
+            }
+            // markers without segments: TEM and RST0..RST7
+            0x01 | 0xd0..=0xd7 => continue,
+            // EXIF is before the image data
+            SOS | EOI => return Err(exif::Error::NotFound("JPEG").into()),
+            SOI => return Err(invalid_data("unexpected start of JPEG image")),
+            _ => {}
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length)
+            .checked_sub(2)
+            .ok_or_else(|| invalid_data("invalid length of JPEG segment"))?;
+        if marker[1] == APP1 {
+            let mut segment = vec![0u8; length as usize];
+            reader.read_exact(&mut segment)?;
+            if segment.starts_with(EXIF_ID) {
+                segment.drain(..EXIF_ID.len());
+                return Ok(segment);
+            }
+        } else {
+            reader.seek(SeekFrom::Current(length as i64))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::File, io::BufReader, io::Cursor};
+
+    #[test]
+    fn exif_segment() {
+        // APP0 (JFIF) is before EXIF
+        let mut reader = BufReader::new(File::open("test_data/images/01.jpg").unwrap());
+        let exif = exif::Reader::new().read_raw(read_jpeg_exif(&mut reader).unwrap()).unwrap();
+        let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY).unwrap();
+        assert_eq!(field.display_value().to_string(), "2020-06-21 22:20:05");
+        // image data isn't read
+        assert!(reader.stream_position().unwrap() < 64 * 1024);
+    }
+
+    #[test]
+    fn no_exif() {
+        let jpeg = [MARKER, SOI, MARKER, 0xe0, 0, 4, 0, 0, MARKER, SOS, 0, 2, 1, 2, 3, MARKER, EOI];
+        assert!(matches!(
+            read_jpeg_exif(&mut Cursor::new(jpeg)),
+            Err(Error::ExifError(exif::Error::NotFound(_)))
+        ));
+        let broken = [MARKER, SOI, MARKER, 0xe0, 0, 1];
+        assert!(read_jpeg_exif(&mut Cursor::new(broken)).is_err());
+    }
+}
@@ -1,36 +1,78 @@
-use std::{fs::File, io::BufReader, path::Path};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+};
 
 use crate::error::Error;
-use crate::exifreader::ExifData;
+use crate::exifreader::Tags;
+
+use super::heifreader::read_heif_exif;
+use super::isobmff::is_heif;
+use super::jpegreader::{is_jpeg, read_jpeg_exif};
+use super::tiffreader::{is_tiff, read_tiff_tags};
+use super::videoreader::{read_video_tags, VideoContainer, HEADER_SIZE};
 
 use super::ExifReader;
-use chrono::NaiveDate;
-use exif::{Exif, In, Tag};
+use exif::{Exif, In, Tag, Value};
 use tracing::trace;
 
 pub struct RustReader;
 
 impl ExifReader for RustReader {
-    fn read<P>(&self, file_path: P) -> Result<ExifData, Error>
+    fn read_tags<P>(&self, file_path: P) -> Result<Tags, Error>
     where
         P: AsRef<std::path::Path>,
     {
-        let file = File::open(file_path.as_ref())?;
-        let buff_capacity = file.metadata()?.len() as usize + 1; // see fs.rs initial_buffer_size
-        let mut reader = BufReader::with_capacity(buff_capacity, file);
-        let exif = exif::Reader::new().read_from_container(&mut reader)?;
-
-
-        let f_datetime = get_field_or_error(&exif, Tag::DateTimeOriginal)?;
-        //let f_camera = get_field_or_error(&exif, Tag::Model)?;
+        // RAW files and videos may be gigabytes, so only the needed parts of the file are read
+        let mut reader = BufReader::new(File::open(file_path.as_ref())?);
+
+        // videos have no EXIF, their dates are in the container
+        let mut header = Vec::new();
+        reader.by_ref().take(HEADER_SIZE).read_to_end(&mut header)?;
+        reader.rewind()?;
+        if let Some(container) = VideoContainer::detect(&header) {
+            let tags = read_video_tags(container, &mut reader)?;
+            trace!("video: {:?}", tags);
+            return Ok(tags);
+        }
+
+        if is_tiff(&header) {
+            let tags = read_tiff_tags(&mut reader)?;
+            trace!("tiff: {:?}", tags);
+            return Ok(tags);
+        }
+
+        // HEIC and AVIF store EXIF as an item of the container
+        let exif = if is_heif(&header) {
+            exif::Reader::new().read_raw(read_heif_exif(&mut reader)?)?
+        } else if is_jpeg(&header) {
+            exif::Reader::new().read_raw(read_jpeg_exif(&mut reader)?)?
+        } else {
+            match exif::Reader::new().read_from_container(&mut reader) {
+                // it's neither PNG nor WebP
+                Err(exif::Error::InvalidFormat("Unknown image format")) => return Err(Error::UnsupportedFormat),
+                result => result?,
+            }
+        };
 
-        trace!("exif: {}", f_datetime);
+        let tags = Tags {
+            date_time_original: get_field(&exif, Tag::DateTimeOriginal),
+            date_time_digitized: get_field(&exif, Tag::DateTimeDigitized),
+            date_time: get_field(&exif, Tag::DateTime),
+            sub_sec_time_original: get_field(&exif, Tag::SubSecTimeOriginal),
+            sub_sec_time_digitized: get_field(&exif, Tag::SubSecTimeDigitized),
+            sub_sec_time: get_field(&exif, Tag::SubSecTime),
+            offset_time_original: get_field(&exif, Tag::OffsetTimeOriginal),
+            offset_time_digitized: get_field(&exif, Tag::OffsetTimeDigitized),
+            offset_time: get_field(&exif, Tag::OffsetTime),
+            model: get_field(&exif, Tag::Model),
+            body_serial_number: get_field(&exif, Tag::BodySerialNumber),
+        };
 
-        let f_date = f_datetime.split_once(' ').unwrap().0;
+        trace!("exif: {:?}", tags);
 
-        Ok(ExifData {
-            date: NaiveDate::parse_from_str(f_date, "%Y-%m-%d").unwrap()
-        })
+        Ok(tags)
     }
 }
 
@@ -53,17 +95,19 @@ fn dump_file<P: AsRef<Path>>(path: P) -> Result<(), exif::Error> {
     Ok(())
 }
 
-fn get_field_or_error(exif: &Exif, tag: Tag) -> Result<String, Error> {
-    let o_field = exif.get_field(tag, In::PRIMARY);
-    match o_field {
-        Some(field) => Ok(field.display_value().to_string()),
-        None => Err(Error::NoFieldError()),
-    }
+// Dates, subseconds, offsets and camera are ASCII, the raw value is returned to parse all the variants written by cameras
+fn get_field(exif: &Exif, tag: Tag) -> Option<String> {
+    exif.get_field(tag, In::PRIMARY).map(|field| match &field.value {
+        Value::Ascii(values) if !values.is_empty() => String::from_utf8_lossy(&values[0]).to_string(),
+        _ => field.display_value().to_string(),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::exifreader::DateSource;
+    use chrono::{Local, NaiveDate, NaiveTime, TimeZone, Utc};
 
     #[test]
     fn read_from_jpeg() {
@@ -71,7 +115,10 @@ mod tests {
         let maybe_exif = reader.read("test_data/images/01.jpg");
         assert!(maybe_exif.is_ok());
         let exif = maybe_exif.unwrap();
-        assert_eq!(exif.date, NaiveDate::from_ymd_opt(2020, 06, 21).unwrap());
+        assert_eq!(exif.datetime.date(), NaiveDate::from_ymd_opt(2020, 6, 21).unwrap());
+        assert_eq!(exif.datetime.time(), NaiveTime::from_hms_opt(22, 20, 5).unwrap());
+        assert_eq!(exif.source, DateSource::DateTimeOriginal);
+        assert_eq!(exif.camera.as_deref(), Some("DSC-RX100M7"));
     }
 
     #[test]
@@ -80,8 +127,46 @@ mod tests {
         let maybe_exif = reader.read("test_data/images/000000581894.jpg");
         assert!(maybe_exif.is_ok());
         let exif = maybe_exif.unwrap();
-        assert_eq!(exif.date, NaiveDate::from_ymd_opt(2021, 04, 22).unwrap());
+        assert_eq!(exif.datetime.date(), NaiveDate::from_ymd_opt(2021, 4, 22).unwrap());
+
+    }
 
+    #[test]
+    fn read_from_heic() {
+        let reader = RustReader {};
+        let exif = reader.read("test_data/images/08.heic").unwrap();
+        assert_eq!(exif.datetime.date(), NaiveDate::from_ymd_opt(2021, 7, 15).unwrap());
+        assert_eq!(exif.offset, chrono::FixedOffset::east_opt(3 * 3600));
+        assert_eq!(exif.camera.as_deref(), Some("iPhone 12"));
+    }
+
+    #[test]
+    fn read_from_avif() {
+        let reader = RustReader {};
+        let exif = reader.read("test_data/images/09.avif").unwrap();
+        assert_eq!(exif.datetime.date(), NaiveDate::from_ymd_opt(2021, 8, 1).unwrap());
+        assert_eq!(exif.camera.as_deref(), Some("Pixel 7"));
+    }
+
+    #[test]
+    fn read_from_mov() {
+        let reader = RustReader {};
+        let exif = reader.read("test_data/images/05.mov").unwrap();
+        assert_eq!(exif.datetime.date(), NaiveDate::from_ymd_opt(2020, 6, 21).unwrap());
+        assert_eq!(exif.datetime.time(), NaiveTime::from_hms_opt(21, 0, 0).unwrap());
+        assert_eq!(exif.source, DateSource::DateTimeOriginal);
+        assert_eq!(exif.camera.as_deref(), Some("iPhone 12"));
+    }
+
+    #[test]
+    fn read_from_mp4_in_local_zone() {
+        let reader = RustReader {};
+        let exif = reader.read("test_data/images/04.mp4").unwrap();
+        let local = Utc.with_ymd_and_hms(2020, 6, 21, 19, 0, 0).unwrap().with_timezone(&Local);
+        assert_eq!(exif.datetime, local.naive_local());
+        assert_eq!(exif.offset, Some(*local.offset()));
+        let zone = chrono::FixedOffset::east_opt(5 * 3600);
+        assert_eq!(exif.datetime_in(zone).date(), NaiveDate::from_ymd_opt(2020, 6, 22).unwrap());
     }
 
     //#[test]
@@ -0,0 +1,225 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use exif::Tag;
+
+use crate::error::Error;
+use crate::exifreader::Tags;
+
+use super::isobmff::invalid_data;
+
+// TIFF and RAW files based on it (DNG, CR2, NEF, ARW, ...). Unlike kamadak-exif, which reads the whole
+// file, only IFD0 and Exif IFD are read here: image data may be in any place of the file.
+
+/// Real IFDs have a few hundreds of entries.
+const MAX_ENTRIES: u16 = 1024;
+/// Dates, offsets and camera names are short.
+const MAX_STRING: u32 = 256;
+
+const ASCII: u16 = 2;
+const LONG: u16 = 4;
+const EXIF_IFD_POINTER: u16 = 0x8769;
+
+/// Check the byte order mark and the magic number. ORF and RW2 are TIFF with their own magic numbers.
+pub(crate) fn is_tiff(header: &[u8]) -> bool {
+    [b"II*\0", b"MM\0*", b"IIRO", b"IIRS", b"MMOR", b"IIU\0"]
+        .iter()
+        .any(|magic| header.starts_with(*magic))
+}
+
+#[derive(Clone, Copy)]
+struct ByteOrder {
+    little_endian: bool,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    /// Value or its offset
+    value: [u8; 4],
+}
+
+/// Read tags of TIFF. `reader` should be at the start of the file.
+pub(crate) fn read_tiff_tags<R: Read + Seek>(reader: &mut R) -> Result<Tags, Error> {
+    let mut header = [0u8; 8];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut header)?;
+    if !is_tiff(&header) {
+        return Err(invalid_data("not a TIFF file"));
+    }
+    let order = ByteOrder {
+        little_endian: header[0] == b'I',
+    };
+
+    let ifd0 = read_ifd(reader, order, order.u32(&header[4..8]))?;
+    let exif_ifd = match find(&ifd0, EXIF_IFD_POINTER) {
+        Some(pointer) if pointer.kind == LONG => read_ifd(reader, order, order.u32(&pointer.value))?,
+        _ => Vec::new(),
+    };
+
+    let mut ascii = |ifd: &[Entry], tag: Tag| match find(ifd, tag.number()) {
+        Some(entry) => read_ascii(reader, order, entry),
+        None => Ok(None),
+    };
+    Ok(Tags {
+        date_time_original: ascii(&exif_ifd, Tag::DateTimeOriginal)?,
+        date_time_digitized: ascii(&exif_ifd, Tag::DateTimeDigitized)?,
+        date_time: ascii(&ifd0, Tag::DateTime)?,
+        sub_sec_time_original: ascii(&exif_ifd, Tag::SubSecTimeOriginal)?,
+        sub_sec_time_digitized: ascii(&exif_ifd, Tag::SubSecTimeDigitized)?,
+        sub_sec_time: ascii(&exif_ifd, Tag::SubSecTime)?,
+        offset_time_original: ascii(&exif_ifd, Tag::OffsetTimeOriginal)?,
+        offset_time_digitized: ascii(&exif_ifd, Tag::OffsetTimeDigitized)?,
+        offset_time: ascii(&exif_ifd, Tag::OffsetTime)?,
+        model: ascii(&ifd0, Tag::Model)?,
+        body_serial_number: ascii(&exif_ifd, Tag::BodySerialNumber)?,
+    })
+}
+
+fn find(ifd: &[Entry], tag: u16) -> Option<&Entry> {
+    ifd.iter().find(|entry| entry.tag == tag)
+}
+
+fn read_ifd<R: Read + Seek>(reader: &mut R, order: ByteOrder, offset: u32) -> Result<Vec<Entry>, Error> {
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    let mut count = [0u8; 2];
+    reader.read_exact(&mut count)?;
+    let count = order.u16(&count);
+    if count > MAX_ENTRIES {
+        return Err(invalid_data("too many entries in TIFF IFD"));
+    }
+
+    let mut entries = vec![0u8; count as usize * 12];
+    reader.read_exact(&mut entries)?;
+    Ok(entries
+        .chunks_exact(12)
+        .map(|entry| Entry {
+            tag: order.u16(&entry[0..2]),
+            kind: order.u16(&entry[2..4]),
+            count: order.u32(&entry[4..8]),
+            value: [entry[8], entry[9], entry[10], entry[11]],
+        })
+        .collect())
+}
+
+// The string lasts to the first NUL, like the raw value of kamadak-exif
+fn read_ascii<R: Read + Seek>(reader: &mut R, order: ByteOrder, entry: &Entry) -> Result<Option<String>, Error> {
+    if entry.kind != ASCII {
+        return Ok(None);
+    }
+
+    let length = entry.count.min(MAX_STRING) as usize;
+    let value = if entry.count <= 4 {
+        entry.value[..length].to_vec()
+    } else {
+        let mut value = vec![0u8; length];
+        reader.seek(SeekFrom::Start(order.u32(&entry.value) as u64))?;
+        reader.read_exact(&mut value)?;
+        value
+    };
+    let end = value.iter().position(|b| *b == 0).unwrap_or(value.len());
+    Ok(Some(String::from_utf8_lossy(&value[..end]).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+
+    // TIFF with IFD0 and Exif IFD at the start and image data after them
+    fn tiff(little_endian: bool, data_size: usize) -> Vec<u8> {
+        let u16 = |v: u16| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+        let u32 = |v: u32| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+        let entry = |tag: u16, kind: u16, count: u32, value: [u8; 4]| {
+            [&u16(tag)[..], &u16(kind), &u32(count), &value].concat()
+        };
+
+        // header 8, IFD0 at 8 (2 + 3 * 12 + 4 = 42), Exif IFD at 50 (2 + 2 * 12 + 4 = 30), strings at 80
+        let mut file = if little_endian { b"II*\0".to_vec() } else { b"MM\0*".to_vec() };
+        file.extend(u32(8));
+        file.extend(u16(3));
+        file.extend(entry(Tag::Model.number(), ASCII, 4, *b"A7\0\0"));
+        file.extend(entry(Tag::DateTime.number(), ASCII, 20, u32(80)));
+        file.extend(entry(EXIF_IFD_POINTER, LONG, 1, u32(50)));
+        file.extend(u32(0));
+        file.extend(u16(2));
+        file.extend(entry(Tag::DateTimeOriginal.number(), ASCII, 20, u32(100)));
+        file.extend(entry(Tag::OffsetTimeOriginal.number(), ASCII, 7, u32(120)));
+        file.extend(u32(0));
+        file.extend(b"2020:06:21 23:00:00\0");
+        file.extend(b"2020:06:21 22:20:05\0");
+        file.extend(b"+03:00\0");
+        file.resize(file.len() + data_size, 0xaa);
+        file
+    }
+
+    // Cursor which counts read bytes
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        read: usize,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            self.read += read;
+            Ok(read)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn both_byte_orders() {
+        for little_endian in [true, false] {
+            let tags = read_tiff_tags(&mut Cursor::new(tiff(little_endian, 0))).unwrap();
+            assert_eq!(tags.model.as_deref(), Some("A7"));
+            assert_eq!(tags.date_time.as_deref(), Some("2020:06:21 23:00:00"));
+            assert_eq!(tags.date_time_original.as_deref(), Some("2020:06:21 22:20:05"));
+            assert_eq!(tags.offset_time_original.as_deref(), Some("+03:00"));
+            assert_eq!(tags.sub_sec_time_original, None);
+        }
+    }
+
+    #[test]
+    fn image_data_is_not_read() {
+        let mut reader = CountingReader {
+            inner: Cursor::new(tiff(true, 16 * 1024 * 1024)),
+            read: 0,
+        };
+        read_tiff_tags(&mut reader).unwrap();
+        assert!(reader.read < 1024, "{} bytes are read", reader.read);
+    }
+
+    #[test]
+    fn broken_tiff() {
+        let mut file = tiff(true, 0);
+        file.truncate(60);
+        assert!(read_tiff_tags(&mut Cursor::new(file)).is_err());
+        assert!(read_tiff_tags(&mut Cursor::new(b"II*\0\xff\xff\xff\xff".to_vec())).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use crate::error::Error;
+use crate::exifreader::Tags;
+
+use super::isobmff::{be_u32, be_u64, for_each_box, invalid_data, is_heif, read_box_content, BoxHeader};
+
+/// Containers of video files which dates can be read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VideoContainer {
+    /// ISO base media file format: MP4, MOV, M4V, 3GP
+    IsoBmff,
+    /// RIFF AVI
+    Avi,
+    /// MPEG transport stream of AVCHD cameras: MTS, M2TS
+    MpegTs,
+}
+
+/// How many bytes of the file are required to detect the container.
+pub(crate) const HEADER_SIZE: u64 = 256;
+
+const TS_SYNC_BYTE: u8 = 0x47;
+const TS_PACKET_SIZE: usize = 188;
+const M2TS_PACKET_SIZE: usize = 192;
+
+/// How many bytes of a transport stream are scanned for the AVCHD metadata.
+const TS_SCAN_LIMIT: u64 = 4 * 1024 * 1024;
+
+// Marker of AVCHD metadata in H.264 SEI
+const MDPM: &[u8] = b"MDPM";
+
+impl VideoContainer {
+    /// Detect container by the first [HEADER_SIZE] bytes of the file.
+    pub(crate) fn detect(header: &[u8]) -> Option<VideoContainer> {
+        // packets of MTS start with the sync byte, M2TS has 4 bytes of timestamp before each packet
+        let sync = |offset: usize, packet_size: usize| {
+            header.get(offset) == Some(&TS_SYNC_BYTE)
+                && header.get(offset + packet_size) == Some(&TS_SYNC_BYTE)
+        };
+
+        if header.len() < 16 {
+            None
+        } else if &header[4..8] == b"ftyp" && !is_heif(header) {
+            Some(VideoContainer::IsoBmff)
+        } else if &header[0..4] == b"RIFF" && &header[8..12] == b"AVI " {
+            Some(VideoContainer::Avi)
+        } else if sync(0, TS_PACKET_SIZE) || sync(4, M2TS_PACKET_SIZE) {
+            Some(VideoContainer::MpegTs)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read dates of the video. `reader` should be at the start of the file.
+pub(crate) fn read_video_tags<R: Read + Seek>(container: VideoContainer, reader: &mut R) -> Result<Tags, Error> {
+    match container {
+        VideoContainer::IsoBmff => read_bmff_tags(reader),
+        VideoContainer::Avi => read_avi_tags(reader),
+        VideoContainer::MpegTs => read_mpegts_tags(reader),
+    }
+}
+
+// ===================== ISO BMFF (MP4, MOV) =====================
+
+
+// Boxes with metadata are small, bigger ones are broken
+const METADATA_BOX_LIMIT: u64 = 1024 * 1024;
+
+#[derive(Default)]
+struct BmffDates {
+    /// com.apple.quicktime.creationdate or ©day, local time with time zone
+    creation_date: Option<String>,
+    model: Option<String>,
+    /// mvhd, seconds since 1904 in UTC
+    created: Option<u64>,
+    modified: Option<u64>,
+    /// tkhd of the first track with the date, if mvhd has no date
+    track_created: Option<u64>,
+}
+
+fn read_bmff_tags<R: Read + Seek>(reader: &mut R) -> Result<Tags, Error> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    let mut dates = BmffDates::default();
+
+    for_each_box(reader, 0, file_end, |reader, header| {
+        if &header.kind == b"moov" {
+            read_moov(reader, header, &mut dates)?;
+        }
+        Ok(())
+    })?;
+
+    // mvhd dates are UTC instants, they are local unless `--time-zone` is set, like exiftool's ones
+    let mvhd_date = |seconds: Option<u64>| seconds.and_then(qt_datetime).map(|dt| format_utc(&dt));
+
+    let created = mvhd_date(dates.created.or(dates.track_created));
+    Ok(Tags {
+        date_time_original: dates.creation_date.or_else(|| created.clone()),
+        date_time_digitized: created,
+        date_time: mvhd_date(dates.modified),
+        model: dates.model,
+        ..Tags::default()
+    })
+}
+
+fn read_moov<R: Read + Seek>(reader: &mut R, moov: &BoxHeader, dates: &mut BmffDates) -> Result<(), Error> {
+    for_each_box(reader, moov.start, moov.end, |reader, header| {
+        match &header.kind {
+            b"mvhd" => {
+                let (created, modified) = read_header_dates(reader, header)?;
+                dates.created = created;
+                dates.modified = modified;
+                Ok(())
+            }
+            b"trak" if dates.track_created.is_none() => for_each_box(reader, header.start, header.end, |reader, header| {
+                if &header.kind == b"tkhd" {
+                    dates.track_created = read_header_dates(reader, header)?.0;
+                }
+                Ok(())
+            }),
+            b"meta" => read_meta(reader, header, dates),
+            b"udta" => for_each_box(reader, header.start, header.end, |reader, header| {
+                match &header.kind {
+                    b"meta" => read_meta(reader, header, dates),
+                    b"\xa9day" => {
+                        // QuickTime text: u16 size, u16 language, text
+                        let content = read_box_content(reader, header, METADATA_BOX_LIMIT)?;
+                        if content.len() > 4 && dates.creation_date.is_none() {
+                            dates.creation_date = Some(String::from_utf8_lossy(&content[4..]).to_string());
+                        }
+                        Ok(())
+                    }
+                    _ => Ok(()),
+                }
+            }),
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Creation and modification time of `mvhd` or `tkhd`, they have the same layout.
+fn read_header_dates<R: Read + Seek>(reader: &mut R, header: &BoxHeader) -> Result<(Option<u64>, Option<u64>), Error> {
+    let content = read_box_content(reader, header, 32)?;
+    let (created, modified) = match content.first() {
+        Some(0) if content.len() >= 12 => (be_u32(&content[4..8]) as u64, be_u32(&content[8..12]) as u64),
+        Some(1) if content.len() >= 20 => (be_u64(&content[4..12]), be_u64(&content[12..20])),
+        _ => return Err(invalid_data("unknown version of movie header")),
+    };
+
+    // zero is "unknown"
+    Ok((Some(created).filter(|s| *s != 0), Some(modified).filter(|s| *s != 0)))
+}
+
+/// QuickTime metadata: `keys` has names of items, `ilst` has values in the same order.
+fn read_meta<R: Read + Seek>(reader: &mut R, meta: &BoxHeader, dates: &mut BmffDates) -> Result<(), Error> {
+    // ISO meta is a full box with 4 bytes of version and flags, QuickTime meta is not
+    let mut probe = [0u8; 8];
+    reader.seek(SeekFrom::Start(meta.start))?;
+    reader.read_exact(&mut probe)?;
+    let start = if &probe[4..8] == b"hdlr" { meta.start } else { meta.start + 4 };
+
+    let mut keys = Vec::<String>::new();
+    let mut values = Vec::<(usize, String)>::new();
+    for_each_box(reader, start, meta.end, |reader, header| {
+        match &header.kind {
+            b"keys" => {
+                let content = read_box_content(reader, header, METADATA_BOX_LIMIT)?;
+                keys = parse_keys(&content);
+            }
+            b"ilst" => for_each_box(reader, header.start, header.end, |reader, item| {
+                let index = be_u32(&item.kind) as usize;
+                let name_is_key = index > 0 && index < 0x10000;
+                if name_is_key || &item.kind == b"\xa9day" {
+                    for_each_box(reader, item.start, item.end, |reader, data| {
+                        if &data.kind == b"data" {
+                            let content = read_box_content(reader, data, METADATA_BOX_LIMIT)?;
+                            if content.len() > 8 {
+                                let value = String::from_utf8_lossy(&content[8..]).to_string();
+                                values.push((if name_is_key { index } else { 0 }, value));
+                            }
+                        }
+                        Ok(())
+                    })?;
+                }
+                Ok(())
+            })?,
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    for (index, value) in values {
+        // index 0 is ©day
+        let key = if index == 0 { "\u{a9}day" } else { keys.get(index - 1).map_or("", |k| k.as_str()) };
+        match key {
+            "com.apple.quicktime.creationdate" | "\u{a9}day" => {
+                dates.creation_date.get_or_insert(value);
+            }
+            "com.apple.quicktime.model" => {
+                dates.model.get_or_insert(value);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// full box: version/flags, entry count, entries of (u32 size, namespace, name)
+fn parse_keys(content: &[u8]) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut offset = 8;
+    while offset + 8 <= content.len() {
+        let size = be_u32(&content[offset..offset + 4]) as usize;
+        if size < 8 || offset + size > content.len() {
+            break;
+        }
+        keys.push(String::from_utf8_lossy(&content[offset + 8..offset + size]).to_string());
+        offset += size;
+    }
+    keys
+}
+
+
+fn qt_datetime(seconds_since_1904: u64) -> Option<DateTime<Utc>> {
+    let epoch = Utc.with_ymd_and_hms(1904, 1, 1, 0, 0, 0).single()?;
+    let seconds = i64::try_from(seconds_since_1904).ok()?;
+    epoch.checked_add_signed(Duration::seconds(seconds))
+}
+
+fn format_utc(datetime: &DateTime<Utc>) -> String {
+    datetime.format("%Y:%m:%d %H:%M:%SZ").to_string()
+}
+
+// ===================== AVI =====================
+
+fn read_avi_tags<R: Read + Seek>(reader: &mut R) -> Result<Tags, Error> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    let mut date = None;
+
+    // RIFF size AVI, then chunks
+    for_each_chunk(reader, 12, file_end, &mut |reader, id, start, end| {
+        match id {
+            // IDIT is in hdrl, ICRD is in INFO
+            b"IDIT" | b"ICRD" if date.is_none() => {
+                let size = (end - start).min(256) as usize;
+                let mut value = vec![0u8; size];
+                reader.read_exact(&mut value)?;
+                date = avi_date(&String::from_utf8_lossy(&value));
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(Tags {
+        date_time_original: date,
+        ..Tags::default()
+    })
+}
+
+/// Visit chunks recursively, `LIST` chunks are entered. `f` gets id and content range of a chunk.
+fn for_each_chunk<R, F>(reader: &mut R, start: u64, end: u64, f: &mut F) -> Result<(), Error>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, &[u8; 4], u64, u64) -> Result<(), Error>,
+{
+    let mut position = start;
+    while position + 8 <= end {
+        reader.seek(SeekFrom::Start(position))?;
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let id = [header[0], header[1], header[2], header[3]];
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        let content_end = (position + 8 + size).min(end);
+
+        if &id == b"LIST" {
+            let mut list_type = [0u8; 4];
+            reader.read_exact(&mut list_type)?;
+            // movi has only frames
+            if &list_type != b"movi" {
+                for_each_chunk(reader, position + 12, content_end, f)?;
+            }
+        } else {
+            f(reader, &id, position + 8, content_end)?;
+        }
+
+        // chunks are aligned by 2 bytes
+        position = position + 8 + size + (size & 1);
+    }
+    Ok(())
+}
+
+// "Sun Jun 21 20:00:00 2020\n" or EXIF-like "2020:06:21 20:00:00"
+fn avi_date(value: &str) -> Option<String> {
+    let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    let datetime = NaiveDateTime::parse_from_str(value, "%a %b %e %H:%M:%S %Y")
+        .ok()
+        .map(|dt| dt.format("%Y:%m:%d %H:%M:%S").to_string());
+    datetime.or_else(|| Some(value.to_string()).filter(|v| !v.is_empty()))
+}
+
+// ===================== MPEG-TS (AVCHD) =====================
+
+fn read_mpegts_tags<R: Read + Seek>(reader: &mut R) -> Result<Tags, Error> {
+    let mut buffer = Vec::new();
+    reader.take(TS_SCAN_LIMIT).read_to_end(&mut buffer)?;
+
+    let date = buffer
+        .windows(MDPM.len())
+        .position(|w| w == MDPM)
+        .and_then(|position| mdpm_date(&buffer[position + MDPM.len()..]));
+
+    Ok(Tags {
+        date_time_original: date.map(|dt| dt.format("%Y:%m:%d %H:%M:%S").to_string()),
+        ..Tags::default()
+    })
+}
+
+/// MDPM has count of entries and entries of 5 bytes: tag and 4 bytes of data. Date is
+/// BCD encoded in tags 0x18 (time zone, century, year, month) and 0x19 (day, h, m, s).
+fn mdpm_date(data: &[u8]) -> Option<NaiveDateTime> {
+    let count = *data.first()? as usize;
+    let entries = data.get(1..1 + count * 5)?;
+
+    let mut ym = None;
+    let mut dhms = None;
+    for entry in entries.chunks(5) {
+        match entry[0] {
+            0x18 => ym = Some([entry[2], entry[3], entry[4]]),
+            0x19 => dhms = Some([entry[1], entry[2], entry[3], entry[4]]),
+            _ => {}
+        }
+    }
+
+    let [century, year, month] = ym?;
+    let [day, hour, minute, second] = dhms?;
+    let year = bcd(century)? as i32 * 100 + bcd(year)? as i32;
+    NaiveDate::from_ymd_opt(year, bcd(month)?, bcd(day)?)?.and_hms_opt(bcd(hour)?, bcd(minute)?, bcd(second)?)
+}
+
+fn bcd(byte: u8) -> Option<u32> {
+    let (high, low) = ((byte >> 4) as u32, (byte & 0x0f) as u32);
+    if high < 10 && low < 10 {
+        Some(high * 10 + low)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::File, io::BufReader};
+
+    fn read(path: &str) -> Tags {
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        let mut header = Vec::new();
+        reader.by_ref().take(HEADER_SIZE).read_to_end(&mut header).unwrap();
+        reader.rewind().unwrap();
+        let container = VideoContainer::detect(&header).unwrap();
+        read_video_tags(container, &mut reader).unwrap()
+    }
+
+    #[test]
+    fn mp4_mvhd() {
+        let tags = read("test_data/images/04.mp4");
+        assert_eq!(tags.date_time_original, Some("2020:06:21 19:00:00Z".to_string()));
+        assert_eq!(tags.date_time_digitized, tags.date_time_original);
+    }
+
+    #[test]
+    fn mov_apple_creation_date() {
+        let tags = read("test_data/images/05.mov");
+        assert_eq!(tags.date_time_original.as_deref(), Some("2020-06-21T21:00:00+0300"));
+        assert_eq!(tags.date_time_digitized, Some("2020:06:21 18:00:00Z".to_string()));
+        assert_eq!(tags.model.as_deref(), Some("iPhone 12"));
+    }
+
+    #[test]
+    fn avi_idit() {
+        let tags = read("test_data/images/06.avi");
+        assert_eq!(tags.date_time_original.as_deref(), Some("2020:06:21 20:00:00"));
+    }
+
+    #[test]
+    fn mts_mdpm() {
+        let tags = read("test_data/images/07.mts");
+        assert_eq!(tags.date_time_original.as_deref(), Some("2020:06:21 20:30:00"));
+    }
+
+    #[test]
+    fn not_a_video() {
+        for path in ["test_data/images/01.jpg", "test_data/images/08.heic"] {
+            let mut header = Vec::new();
+            File::open(path).unwrap().take(HEADER_SIZE).read_to_end(&mut header).unwrap();
+            assert_eq!(VideoContainer::detect(&header), None, "{}", path);
+        }
+        assert_eq!(VideoContainer::detect(b"GIF89a\x01\x00\x01\x00\x80\x00\x00\xff\xff\xff"), None);
+    }
+}
@@ -1,67 +1,380 @@
 use std::{
-    fs, io,
+    cell::RefCell,
+    collections::HashSet,
+    fmt,
+    fs::{self, DirEntry, Metadata},
+    io,
     path::{Path, PathBuf},
-    collections::HashMap,
+    str::FromStr,
 };
 
 use chrono::NaiveDate;
 use dashmap::DashMap;
-use regex::Regex;
+use tracing::warn;
 
+use crate::{
+    layout::Layout,
+    recognizers::{FolderRecognizer, Recognized},
+    FileType,
+};
+
+/// Folders of the template and recognized folders may be nested up to this level, like
+/// `2022/10/02 Pushkin/raw`.
+const MAX_RECOGNIZED_DEPTH: usize = 4;
+
+/// What to do with symbolic links in source folders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Links to files and folders are ignored
+    Skip,
+    /// Linked folders are scanned, links to files are arranged as files (the link is moved)
+    Follow,
+}
 
-lazy_static! {
-    static ref DATE_PREFIX: Regex = Regex::new(r"^(\d{4}-\d{2}-\d{2}).*").unwrap();
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SymlinkPolicy::Skip => "skip",
+            SymlinkPolicy::Follow => "follow",
+        })
+    }
 }
 
-fn try_extract_date(path_str: &str) -> Option<NaiveDate> {
-    let x: Option<regex::Captures> = DATE_PREFIX.captures_iter(path_str).next();
-    return x.map(
-        |c| NaiveDate::parse_from_str(&c[1], "%Y-%m-%d").unwrap(), // impossible error
-    );
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip" => Ok(SymlinkPolicy::Skip),
+            "follow" => Ok(SymlinkPolicy::Follow),
+            _ => Err(format!("unknown symlink policy '{}', expected skip or follow", s)),
+        }
+    }
 }
 
-#[derive(Debug,Clone, Copy, Hash, PartialEq, Eq)]
-pub(crate) enum TargetType {
-    IMAGE(NaiveDate),
-    RAW(NaiveDate),
+impl SymlinkPolicy {
+    /// Metadata of the entry, links are followed by [SymlinkPolicy::Follow] only.
+    pub(crate) fn metadata(&self, entry: &DirEntry) -> io::Result<Metadata> {
+        match self {
+            SymlinkPolicy::Skip => entry.metadata(),
+            SymlinkPolicy::Follow => fs::metadata(entry.path()),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub(crate) struct Folders {
+    /// Folders with files to arrange, parents are before their children. The work dir
+    /// itself is not included.
     pub source: Vec<PathBuf>,
-    pub target: DashMap<TargetType,PathBuf>,
+    /// Existing folders of the layout by their paths without events, like `2022-10-02/raw` for
+    /// `2022-10-02 (Pushkin)/raw`. Parents are included, like `2022` for `{year}/{date}`.
+    pub target: DashMap<PathBuf, PathBuf>,
 }
 
-pub(crate) fn find_folders<P>(entry_point: &P, raw_folder: &str) -> io::Result<Folders>
+/// Find folders of the layout and folders to arrange.
+///
+/// Folders of the layout are looked for from `entry_point` by the template and `recognizers`,
+/// other folders in `entry_point` are scanned recursively up to `max_depth` levels (`1` is the
+/// children of `entry_point`, `None` is unlimited). With `Some(0)` only folders of the layout
+/// are found.
+pub(crate) fn find_folders<P>(
+    entry_point: &P,
+    layout: &Layout,
+    recognizers: &[Box<dyn FolderRecognizer>],
+    raw_folder: &str,
+    video_folder: &str,
+    max_depth: Option<usize>,
+    symlinks: SymlinkPolicy,
+) -> io::Result<Folders>
 where
     P: AsRef<Path>,
 {
-    let mut target_folders = DashMap::<TargetType,PathBuf>::new();
+    let targets = TargetSearch {
+        work_dir: entry_point.as_ref(),
+        layout,
+        recognizers,
+        raw_folder,
+        video_folder,
+        symlinks,
+        found: DashMap::new(),
+        recognized: RefCell::new(Vec::new()),
+    };
     let mut source_folders = Vec::new();
-
+    // linked folders may make a cycle
+    let mut visited = HashSet::new();
+    visited.insert(fs::canonicalize(entry_point)?);
 
     for entry in fs::read_dir(entry_point)? {
         let entry = entry?;
         let path = entry.path();
         if let Some(path_str) = entry.file_name().to_str() {
-            if path.is_dir() {
+            if is_folder(&entry, symlinks) {
                 tracing::debug!(folder=path_str);
-                match try_extract_date(path_str) {
-                    Some(date) => {
-                         target_folders.insert(TargetType::IMAGE(date), path.to_path_buf());
-                         let raw_folder = path.join(raw_folder);
-                         if raw_folder.is_dir() {
-                            target_folders.insert(TargetType::RAW(date), path.to_path_buf());
-                         }
-                    },
-                    None => source_folders.push(path),
+                // a folder is a target if it has folders of files, like `2022/2022-10-02` of
+                // `{year}/{date}`, otherwise `2022` may be a folder to arrange
+                let matched = targets.match_top(path_str);
+                let is_target = targets.add_matched(path.clone(), 0, matched, Path::new(""));
+                if !is_target && max_depth != Some(0) {
+                    collect_sources(path, 1, max_depth, symlinks, &mut visited, &mut source_folders)
                 }
             }
         }
     }
 
+    // folders of the template win over recognized ones, and folders of one day win over ranges
+    let mut recognized = targets.recognized.take();
+    recognized.sort_by_key(|(days, _, _)| *days);
+    for (_, key, folder) in recognized {
+        targets.found.entry(key).or_insert(folder);
+    }
+
     Ok(Folders {
         source: source_folders,
-        target: target_folders,
+        target: targets.found,
     })
-}
\ No newline at end of file
+}
+
+struct TargetSearch<'a> {
+    work_dir: &'a Path,
+    layout: &'a Layout,
+    recognizers: &'a [Box<dyn FolderRecognizer>],
+    raw_folder: &'a str,
+    video_folder: &'a str,
+    symlinks: SymlinkPolicy,
+    found: DashMap<PathBuf, PathBuf>,
+    /// Recognized folders with number of their days, they are added after the search
+    recognized: RefCell<Vec<(i64, PathBuf, PathBuf)>>,
+}
+
+impl TargetSearch<'_> {
+    fn subfolder_name(&self, file_type: FileType) -> &str {
+        match file_type {
+            FileType::RAW => self.raw_folder,
+            _ => self.video_folder,
+        }
+    }
+
+    // Match the folder in the work dir, `{raw?}` and `{video?}` may be omitted
+    fn match_top(&self, name: &str) -> Option<(usize, String)> {
+        for level in 0..self.layout.depth() {
+            match self.layout.subfolder_at(level) {
+                Some(file_type) if self.subfolder_name(file_type) == name => return Some((level, name.to_string())),
+                Some(_) => continue,
+                None => return self.layout.match_folder(level, name).map(|key| (level, key)),
+            }
+        }
+        None
+    }
+
+    // Add the folder if it's matched by the template at the level or recognized, return true if
+    // the folder is added. A recognized range wins over the template, because `{date} {event}`
+    // matches `2022-10-01..2022-10-05` as the first day.
+    fn add_matched(&self, folder: PathBuf, level: usize, matched: Option<(usize, String)>, parent_key: &Path) -> bool {
+        let recognized = match self.layout.date_level() {
+            Some(date_level) if level <= date_level => self.recognize(&folder),
+            _ => None,
+        };
+
+        if let Some(Recognized::Days(first, last)) = recognized {
+            if first < last {
+                return self.add_days(&folder, first, last);
+            }
+        }
+        if let Some((level, name)) = matched {
+            if self.add(folder.clone(), level, parent_key, &name) {
+                return true;
+            }
+        }
+
+        match recognized {
+            Some(Recognized::Days(first, last)) => self.add_days(&folder, first, last),
+            Some(Recognized::Parent) => self.add_recognized_children(&folder),
+            None => false,
+        }
+    }
+
+    fn recognize(&self, folder: &Path) -> Option<Recognized> {
+        let relative = folder.strip_prefix(self.work_dir).ok()?;
+        if relative.components().count() > MAX_RECOGNIZED_DEPTH {
+            return None;
+        }
+        self.recognizers
+            .iter()
+            .find_map(|recognizer| recognizer.recognize(relative))
+    }
+
+    // Add the recognized folder for each day with subfolders `{raw?}` and `{video?}` below the
+    // folder of the day
+    fn add_days(&self, folder: &Path, first: NaiveDate, last: NaiveDate) -> bool {
+        let date_level = self.layout.date_level().unwrap(); // recognized only if it's known
+        let days = (last - first).num_days() + 1;
+        let mut recognized = self.recognized.borrow_mut();
+        for date in first.iter_days().take(days as usize) {
+            let key: PathBuf = match self.layout.date_folders(date) {
+                Some(folders) => folders.iter().collect(),
+                None => continue,
+            };
+
+            let mut level = date_level + 1;
+            while let Some(file_type) = self.layout.subfolder_at(level) {
+                let name = self.subfolder_name(file_type);
+                if folder.join(name).is_dir() {
+                    recognized.push((days, key.join(name), folder.join(name)));
+                }
+                level += 1;
+            }
+            recognized.push((days, key, folder.to_path_buf()));
+        }
+        true
+    }
+
+    // Add recognized children of the folder like `2022`, return true if any of them is added
+    fn add_recognized_children(&self, folder: &Path) -> bool {
+        let entries = match fs::read_dir(folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Can't read folder {}, error: {}", folder.to_string_lossy(), e);
+                return false;
+            }
+        };
+
+        let mut is_found = false;
+        for entry in entries.flatten() {
+            if is_folder(&entry, self.symlinks) {
+                let path = entry.path();
+                is_found |= match self.recognize(&path) {
+                    Some(Recognized::Days(first, last)) => self.add_days(&path, first, last),
+                    Some(Recognized::Parent) => self.add_recognized_children(&path),
+                    None => false,
+                };
+            }
+        }
+        is_found
+    }
+
+    // Add the folder matched at the level and look for its children by the next levels. `name`
+    // is the folder's name without the event. Folders without folders of files are not added,
+    // return true if the folder is added.
+    fn add(&self, folder: PathBuf, level: usize, parent_key: &Path, name: &str) -> bool {
+        let key = parent_key.join(name);
+        let is_found = self.add_children(&folder, level, &key) || self.layout.is_last(level);
+        // a folder of events only has no own key, like `Trip` of `{year}/{event}/{date}`
+        if is_found && !name.is_empty() {
+            self.found.insert(key, folder);
+        }
+        is_found
+    }
+
+    fn add_children(&self, folder: &Path, level: usize, key: &Path) -> bool {
+        let mut is_found = false;
+
+        // subfolders are checked by names, other folders are read only if there is a level for them
+        let mut level = level + 1;
+        while let Some(file_type) = self.layout.subfolder_at(level) {
+            let name = self.subfolder_name(file_type);
+            let subfolder = folder.join(name);
+            if subfolder.is_dir() {
+                is_found |= self.add(subfolder, level, key, name);
+            }
+            level += 1;
+        }
+        if level >= self.layout.depth() {
+            return is_found;
+        }
+
+        let entries = match fs::read_dir(folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Can't read folder {}, error: {}", folder.to_string_lossy(), e);
+                return is_found;
+            }
+        };
+        for entry in entries.flatten() {
+            if let Some(name) = entry.file_name().to_str() {
+                if is_folder(&entry, self.symlinks) {
+                    let matched = self.layout.match_folder(level, name).map(|name| (level, name));
+                    is_found |= self.add_matched(entry.path(), level, matched, key);
+                }
+            }
+        }
+        is_found
+    }
+}
+
+/// Find folders to import from `roots` recursively up to `max_depth` levels. The roots
+/// themselves are not included, a root inside another one is scanned once.
+pub(crate) fn find_sources(roots: &[PathBuf], max_depth: Option<usize>, symlinks: SymlinkPolicy) -> Vec<PathBuf> {
+    let mut source_folders = Vec::new();
+    let mut visited = HashSet::new();
+    for root in roots {
+        match fs::canonicalize(root) {
+            Ok(canonical) => {
+                visited.insert(canonical);
+            }
+            Err(e) => warn!("Can't read folder {}, error: {}", root.to_string_lossy(), e),
+        }
+    }
+
+    if max_depth == Some(0) {
+        return source_folders;
+    }
+
+    for root in roots {
+        let entries = match fs::read_dir(root) {
+            Ok(entries) => entries,
+            Err(_) => continue, // already reported
+        };
+        for entry in entries.flatten() {
+            if is_folder(&entry, symlinks) {
+                collect_sources(entry.path(), 1, max_depth, symlinks, &mut visited, &mut source_folders);
+            }
+        }
+    }
+    source_folders
+}
+
+fn is_folder(entry: &DirEntry, symlinks: SymlinkPolicy) -> bool {
+    symlinks.metadata(entry).map(|m| m.is_dir()).unwrap_or(false)
+}
+
+// Add the folder and its subfolders to sources. Unreadable folders are skipped with a warning.
+fn collect_sources(
+    folder: PathBuf,
+    depth: usize,
+    max_depth: Option<usize>,
+    symlinks: SymlinkPolicy,
+    visited: &mut HashSet<PathBuf>,
+    sources: &mut Vec<PathBuf>,
+) {
+    match fs::canonicalize(&folder) {
+        Ok(canonical) => {
+            if !visited.insert(canonical) {
+                return;
+            }
+        }
+        Err(e) => {
+            warn!("Can't read folder {}, error: {}", folder.to_string_lossy(), e);
+            return;
+        }
+    }
+
+    sources.push(folder.clone());
+    if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+        return;
+    }
+
+    let entries = match fs::read_dir(&folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Can't read folder {}, error: {}", folder.to_string_lossy(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        if is_folder(&entry, symlinks) {
+            collect_sources(entry.path(), depth + 1, max_depth, symlinks, visited, sources);
+        }
+    }
+}
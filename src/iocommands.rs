@@ -1,12 +1,84 @@
-use std::{fs, path::PathBuf};
-use tracing::{info};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tracing::{debug, info};
 
-use crate::error::Error;
+use crate::error::{Context, Error, Operation};
+
+/// How a file is transferred to its folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferMode {
+    /// Rename the file. If the target is on another file system, the file is copied, verified
+    /// and deleted.
+    Move,
+    /// Copy the file, the source is kept.
+    Copy,
+    /// Copy the file, compare contents of the copy and the source, then delete the source.
+    CopyVerifyDelete,
+    /// Make a hard link, the source is kept. If the target is on another file system, the file
+    /// is copied.
+    HardLink,
+    /// Make a copy-on-write clone (btrfs, xfs), the source is kept. If the file system
+    /// doesn't support it, the file is copied.
+    RefLink,
+}
+
+impl fmt::Display for TransferMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TransferMode::Move => "move",
+            TransferMode::Copy => "copy",
+            TransferMode::CopyVerifyDelete => "copy-verify-delete",
+            TransferMode::HardLink => "hardlink",
+            TransferMode::RefLink => "reflink",
+        })
+    }
+}
+
+impl FromStr for TransferMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "move" => Ok(TransferMode::Move),
+            "copy" => Ok(TransferMode::Copy),
+            "copy-verify-delete" | "verify" => Ok(TransferMode::CopyVerifyDelete),
+            "hardlink" | "link" => Ok(TransferMode::HardLink),
+            "reflink" | "clone" => Ok(TransferMode::RefLink),
+            _ => Err(format!(
+                "unknown transfer mode '{}', expected one of: move, copy, copy-verify-delete, hardlink, reflink",
+                s
+            )),
+        }
+    }
+}
+
+impl TransferMode {
+    /// Is the source deleted after the transfer.
+    pub(crate) fn removes_source(&self) -> bool {
+        matches!(self, TransferMode::Move | TransferMode::CopyVerifyDelete)
+    }
+}
 
 #[derive(Debug)]
-pub(crate) struct MoveFile {
+pub(crate) struct TransferFile {
     pub(crate) from: PathBuf,
     pub(crate) to: PathBuf,
+    pub(crate) mode: TransferMode,
+    /// Replace the existing file `to`
+    pub(crate) overwrite: bool,
+}
+
+/// Remove the file, it's a duplicate of the file in the target folder.
+#[derive(Debug)]
+pub(crate) struct RmFile {
+    pub(crate) target: PathBuf,
+    /// The file which is kept
+    pub(crate) duplicate_of: PathBuf,
 }
 
 #[derive(Debug)]
@@ -14,6 +86,7 @@ pub(crate) struct MkDir {
     pub(crate) target: PathBuf,
 }
 
+#[derive(Debug)]
 pub(crate) struct RmEmptyDir {
     pub(crate) target: PathBuf,
 }
@@ -22,19 +95,230 @@ pub(crate) trait IOCommand {
     fn exec(&self, dry_run: bool) -> Result<(), Error>;
 }
 
-impl IOCommand for MoveFile {
+impl IOCommand for TransferFile {
     fn exec(&self, dry_run: bool) -> Result<(), Error> {
         if dry_run {
             let from = &self.from;
             let to = &self.to;
-            info!("{} ➙ {}", from.to_string_lossy(), to.to_string_lossy());
-            Ok(())
-        } else {
-            fs::rename(&self.from, &self.to).map_err(Error::from)
+            let replace = if self.overwrite { ", replace" } else { "" };
+            info!("{} ➙ {} ({}{})", from.to_string_lossy(), to.to_string_lossy(), self.mode, replace);
+            return Ok(());
+        }
+
+        let (from, to) = (&self.from, &self.to);
+        match (self.mode, self.overwrite) {
+            (mode, false) => transfer(mode, from, to),
+            // rename replaces the file itself
+            (TransferMode::Move, true) => match fs::rename(from, to) {
+                Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                    replace(TransferMode::CopyVerifyDelete, from, to)
+                }
+                result => result,
+            },
+            (mode, true) => replace(mode, from, to),
         }
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => Error::Conflict { target: to.clone() },
+            _ => Error::Io(e),
+        })
+        .context(Operation::Transfer(self.mode), from)
+    }
+}
+
+// Existing files are never replaced: other files of the run may be placed at the same path
+fn transfer(mode: TransferMode, from: &Path, to: &Path) -> io::Result<()> {
+    match mode {
+        TransferMode::Move => match rename_no_replace(from, to) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                debug!("{} is on another file system, copy it", from.to_string_lossy());
+                copy_verify_delete(from, to)
+            }
+            result => result,
+        },
+        TransferMode::Copy => copy_file(from, to),
+        TransferMode::CopyVerifyDelete => copy_verify_delete(from, to),
+        TransferMode::HardLink => match fs::hard_link(from, to) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => copy_file(from, to),
+            result => result,
+        },
+        TransferMode::RefLink => reflink(from, to).or_else(|e| {
+            debug!("can't clone {}, copy it, error: {}", from.to_string_lossy(), e);
+            copy_file(from, to)
+        }),
+    }
+}
+
+/// Rename the file if `to` doesn't exist. Without `RENAME_NOREPLACE` the file is linked and
+/// unlinked, if the file system doesn't support links, it's copied.
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    match rename_at_no_replace(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => match fs::hard_link(from, to) {
+            Ok(_) => fs::remove_file(from),
+            Err(e) if matches!(e.kind(), io::ErrorKind::AlreadyExists | io::ErrorKind::CrossesDevices) => Err(e),
+            Err(e) => {
+                debug!("can't link {}, copy it, error: {}", from.to_string_lossy(), e);
+                copy_verify_delete(from, to)
+            }
+        },
+        result => result,
     }
 }
 
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn rename_at_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid C strings while the call
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        // old kernel or the file system doesn't support the flag
+        Some(libc::EINVAL | libc::ENOSYS) => Err(io::Error::new(io::ErrorKind::Unsupported, e)),
+        _ => Err(e),
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn rename_at_no_replace(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "RENAME_NOREPLACE is supported on linux only"))
+}
+
+/// Transfer the file to a temporary file near `to` and replace `to` with it, so the existing
+/// file is kept if the transfer fails. The source is deleted after the replacement.
+fn replace(mode: TransferMode, from: &Path, to: &Path) -> io::Result<()> {
+    let mut temporary = to.as_os_str().to_os_string();
+    temporary.push(".photoman-tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let kept_mode = match mode {
+        TransferMode::Move | TransferMode::CopyVerifyDelete => TransferMode::Copy,
+        mode => mode,
+    };
+    let replaced = transfer(kept_mode, from, &temporary)
+        .and_then(|_| match mode.removes_source() {
+            true => verify_copy(from, &temporary),
+            false => Ok(()),
+        })
+        .and_then(|_| fs::rename(&temporary, to));
+
+    if replaced.is_err() {
+        let _ = fs::remove_file(&temporary);
+        return replaced;
+    }
+    match mode.removes_source() {
+        true => fs::remove_file(from),
+        false => Ok(()),
+    }
+}
+
+/// Copy the file with its modification time (it's a source of date), an existing file is
+/// never overwritten.
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut source = File::open(from)?;
+    let mut target = File::create_new(to)?;
+
+    let copied = io::copy(&mut source, &mut target)
+        .and_then(|_| source.metadata())
+        .and_then(|metadata| {
+            target.set_permissions(metadata.permissions())?;
+            target.set_modified(metadata.modified()?)?;
+            target.sync_all()
+        });
+
+    if copied.is_err() {
+        let _ = fs::remove_file(to);
+    }
+    copied
+}
+
+fn copy_verify_delete(from: &Path, to: &Path) -> io::Result<()> {
+    copy_file(from, to)?;
+    if let Err(e) = verify_copy(from, to) {
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+    fs::remove_file(from)
+}
+
+fn verify_copy(from: &Path, copy: &Path) -> io::Result<()> {
+    if !same_content(from, copy)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("copy of {} differs from the source", from.to_string_lossy()),
+        ));
+    }
+    Ok(())
+}
+
+/// Compare contents of files byte by byte, it's used to check a copy before its source is deleted
+/// and to find duplicates.
+pub(crate) fn same_content<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let (mut chunk_a, mut chunk_b) = (vec![0u8; 256 * 1024], vec![0u8; 256 * 1024]);
+    loop {
+        let (read_a, read_b) = (read_chunk(&mut a, &mut chunk_a)?, read_chunk(&mut b, &mut chunk_b)?);
+        if chunk_a[..read_a] != chunk_b[..read_b] {
+            return Ok(false);
+        }
+        if read_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+// Fill the buffer unless the file ends, so chunks of both files have the same offsets
+fn read_chunk(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = File::open(from)?;
+    let target = File::create_new(to)?;
+    // SAFETY: both descriptors are open while the files are alive
+    let result = unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if result != 0 {
+        let e = io::Error::last_os_error();
+        drop(target);
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+    target.set_modified(source.metadata()?.modified()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "clones are supported on linux only"))
+}
+
 impl IOCommand for MkDir {
     fn exec(&self, dry_run: bool) -> Result<(), Error> {
         if dry_run {
@@ -44,7 +328,18 @@ impl IOCommand for MkDir {
             );
             Ok(())
         } else {
-            fs::create_dir_all(&self.target).map_err(Error::from)
+            fs::create_dir_all(&self.target).context(Operation::MkDir, &self.target)
+        }
+    }
+}
+
+impl IOCommand for RmFile {
+    fn exec(&self, dry_run: bool) -> Result<(), Error> {
+        if dry_run {
+            info!("remove duplicate: {}", self.target.to_string_lossy());
+            Ok(())
+        } else {
+            fs::remove_file(&self.target).context(Operation::RemoveFile, &self.target)
         }
     }
 }
@@ -52,9 +347,149 @@ impl IOCommand for MkDir {
 impl IOCommand for RmEmptyDir {
     fn exec(&self, dry_run: bool) -> Result<(), Error> {
         if !dry_run {
-            fs::remove_dir(&self.target).map_err(Error::from)
+            fs::remove_dir(&self.target).context(Operation::RemoveDir, &self.target)
         } else {
             Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::test_dir;
+
+    fn prepare(test_name: &str) -> (PathBuf, PathBuf) {
+        let dir = test_dir("iocommands", test_name);
+        let from = dir.join("01.jpg");
+        fs::copy("test_data/images/01.jpg", &from).unwrap();
+        (from, dir.join("copy.jpg"))
+    }
+
+    fn transfer(from: &Path, to: &Path, mode: TransferMode) -> Result<(), Error> {
+        TransferFile {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            mode,
+            overwrite: false,
+        }
+        .exec(false)
+    }
+
+    #[test]
+    fn copy_keeps_source_and_modification_time() {
+        let (from, to) = prepare("copy");
+        transfer(&from, &to, TransferMode::Copy).unwrap();
+        assert!(from.exists());
+        assert!(same_content(&from, &to).unwrap());
+        assert_eq!(
+            fs::metadata(&from).unwrap().modified().unwrap(),
+            fs::metadata(&to).unwrap().modified().unwrap()
+        );
+    }
+
+    #[test]
+    fn copy_verify_delete_removes_source() {
+        let (from, to) = prepare("copy_verify_delete");
+        let content = fs::read(&from).unwrap();
+        transfer(&from, &to, TransferMode::CopyVerifyDelete).unwrap();
+        assert!(!from.exists());
+        assert_eq!(fs::read(&to).unwrap(), content);
+    }
+
+    #[test]
+    fn existing_file_is_not_overwritten() {
+        let (from, to) = prepare("existing_file");
+        fs::write(&to, "other file").unwrap();
+        for mode in [TransferMode::Copy, TransferMode::CopyVerifyDelete, TransferMode::RefLink] {
+            assert!(transfer(&from, &to, mode).is_err(), "{}", mode);
+            assert!(from.exists());
+            assert_eq!(fs::read_to_string(&to).unwrap(), "other file");
+        }
+    }
+
+    #[test]
+    fn existing_file_is_replaced() {
+        for mode in [TransferMode::Move, TransferMode::Copy, TransferMode::CopyVerifyDelete, TransferMode::HardLink] {
+            let (from, to) = prepare(&format!("replace_{}", mode));
+            let content = fs::read(&from).unwrap();
+            fs::write(&to, "other file").unwrap();
+
+            TransferFile {
+                from: from.clone(),
+                to: to.clone(),
+                mode,
+                overwrite: true,
+            }
+            .exec(false)
+            .unwrap();
+            assert_eq!(fs::read(&to).unwrap(), content, "{}", mode);
+            assert_eq!(from.exists(), !mode.removes_source(), "{}", mode);
+            assert!(!to.with_file_name("copy.jpg.photoman-tmp").exists());
+        }
+    }
+
+    #[test]
+    fn existing_file_is_kept() {
+        for mode in [
+            TransferMode::Move,
+            TransferMode::Copy,
+            TransferMode::CopyVerifyDelete,
+            TransferMode::HardLink,
+            TransferMode::RefLink,
+        ] {
+            let (from, to) = prepare(&format!("keep_{}", mode));
+            fs::write(&to, "other file").unwrap();
+
+            let e = transfer(&from, &to, mode).unwrap_err();
+            assert!(matches!(e.cause(), Error::Conflict { target } if *target == to), "{}", mode);
+            assert_eq!(fs::read(&to).unwrap(), b"other file", "{}", mode);
+            assert!(from.exists(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn content_is_compared() {
+        let (from, to) = prepare("content_is_compared");
+        fs::write(&to, fs::read(&from).unwrap()).unwrap();
+        assert!(same_content(&from, &to).unwrap());
+
+        // the same size, one byte differs
+        let mut other = fs::read(&from).unwrap();
+        let last = other.len() - 1;
+        other[last] ^= 1;
+        fs::write(&to, other).unwrap();
+        assert!(!same_content(&from, &to).unwrap());
+        fs::write(&to, "other file").unwrap();
+        assert!(!same_content(&from, &to).unwrap());
+    }
+
+    #[test]
+    fn rename_without_replace() {
+        let (from, to) = prepare("rename_without_replace");
+        rename_no_replace(&from, &to).unwrap();
+        assert!(!from.exists());
+        assert!(to.exists());
+    }
+
+    #[test]
+    fn links_and_clones() {
+        let (from, to) = prepare("links");
+        transfer(&from, &to, TransferMode::HardLink).unwrap();
+        assert!(from.exists());
+        assert!(same_content(&from, &to).unwrap());
+
+        // a clone falls back to a copy if the file system doesn't support it
+        let clone = to.with_file_name("clone.jpg");
+        transfer(&from, &clone, TransferMode::RefLink).unwrap();
+        assert!(from.exists());
+        assert!(same_content(&from, &clone).unwrap());
+    }
+
+    #[test]
+    fn modes_by_name() {
+        assert_eq!("copy-verify-delete".parse::<TransferMode>(), Ok(TransferMode::CopyVerifyDelete));
+        assert_eq!("HardLink".parse::<TransferMode>(), Ok(TransferMode::HardLink));
+        assert!("symlink".parse::<TransferMode>().is_err());
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{error::Error, iocommands::TransferMode, json};
+
+/// Journal of executed commands in the work dir, one JSON object per line. It's appended by
+/// every run, `undo` reverts the last run which isn't undone yet.
+pub(crate) const JOURNAL_NAME: &str = ".photoman-journal.jsonl";
+
+/// A record of the journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub(crate) enum Record {
+    /// Start of the run, commands of the run follow it
+    Run { time: String },
+    /// The folder is created, it didn't exist
+    MkDir {
+        #[serde(with = "json::path")]
+        path: PathBuf,
+    },
+    Transfer {
+        #[serde(with = "json::name")]
+        mode: TransferMode,
+        #[serde(with = "json::path")]
+        from: PathBuf,
+        #[serde(with = "json::path")]
+        to: PathBuf,
+        /// The existing file is replaced, it can't be restored
+        overwrite: bool,
+    },
+    /// The duplicate of the existing file `copy_of` is removed
+    #[serde(rename = "rm")]
+    RmFile {
+        #[serde(with = "json::path")]
+        path: PathBuf,
+        #[serde(with = "json::path")]
+        copy_of: PathBuf,
+    },
+    /// The emptied source folder is removed
+    RmDir {
+        #[serde(with = "json::path")]
+        path: PathBuf,
+    },
+    /// The last run before it, which isn't undone, is undone
+    Undo { time: String },
+}
+
+/// Append-only journal, records are written as soon as their commands are executed.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    file: Mutex<JournalFile>,
+}
+
+#[derive(Debug)]
+struct JournalFile {
+    file: File,
+    /// Start of the run, it's written with the first command, so runs without commands
+    /// aren't journaled
+    run: Option<Record>,
+}
+
+impl Journal {
+    /// Open the journal of the work dir to append records.
+    pub(crate) fn open(work_dir: &Path) -> io::Result<Journal> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(work_dir.join(JOURNAL_NAME))?;
+        // the record cut by a crash is finished, so it doesn't break the next one
+        if file.seek(SeekFrom::End(0))? > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(Journal {
+            file: Mutex::new(JournalFile { file, run: None }),
+        })
+    }
+
+    /// Open the journal of the work dir and start the run.
+    pub(crate) fn start(work_dir: &Path) -> io::Result<Journal> {
+        let journal = Journal::open(work_dir)?;
+        journal.file.lock().unwrap().run = Some(Record::Run { time: now() });
+        Ok(journal)
+    }
+
+    pub(crate) fn write(&self, record: &Record) -> io::Result<()> {
+        let mut journal = self.file.lock().unwrap();
+        let mut lines = String::new();
+        if let Some(run) = &journal.run {
+            lines.push_str(&serde_json::to_string(run)?);
+            lines.push('\n');
+        }
+        lines.push_str(&serde_json::to_string(record)?);
+        lines.push('\n');
+        // one write per record, so records of parallel commands aren't mixed
+        journal.file.write_all(lines.as_bytes())?;
+        journal.run = None;
+        Ok(())
+    }
+
+    /// Write the record of the executed command, an error is logged.
+    pub(crate) fn record(&self, record: Record) {
+        if let Err(e) = self.write(&record) {
+            warn!("Can't write the journal, record {:?} is lost, error: {}", record, e);
+        }
+    }
+
+    /// Flush records to the disk.
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.file.lock().unwrap().file.sync_data()
+    }
+}
+
+/// Records of the last run which isn't undone, None if all runs are undone. Runs without
+/// commands are skipped, the journals of older versions have them. The broken record which is
+/// the last one of its process, it's cut by a crash, is skipped with a warning.
+pub(crate) fn last_run(work_dir: &Path) -> Result<Option<Vec<Record>>, Error> {
+    let path = work_dir.join(JOURNAL_NAME);
+    let content = fs::read_to_string(&path)?;
+    let broken = |line: usize, message: String| Error::JournalError {
+        path: path.clone(),
+        line,
+        message,
+    };
+
+    let mut runs: Vec<Vec<Record>> = Vec::new();
+    let skip_empty = |runs: &mut Vec<Vec<Record>>| {
+        while runs.last().is_some_and(Vec::is_empty) {
+            runs.pop();
+        }
+    };
+    // the broken record is an error if records of its run follow it
+    let mut cut = None;
+    let skip_cut = |cut: &mut Option<(usize, String)>| {
+        if let Some((line, message)) = cut.take() {
+            warn!("The journal {} is broken at line {}, the record is skipped: {}", path.to_string_lossy(), line, message);
+        }
+    };
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) => match cut {
+                Some((line, message)) => return Err(broken(line, message)),
+                None => {
+                    cut = Some((i + 1, e.to_string()));
+                    continue;
+                }
+            },
+        };
+        match record {
+            // runs and undo are written by other processes
+            Record::Run { .. } => {
+                skip_cut(&mut cut);
+                runs.push(Vec::new());
+            }
+            Record::Undo { .. } => {
+                skip_cut(&mut cut);
+                skip_empty(&mut runs);
+                runs.pop();
+            }
+            record => {
+                if let Some((line, message)) = cut {
+                    return Err(broken(line, message));
+                }
+                match runs.last_mut() {
+                    Some(run) => run.push(record),
+                    None => return Err(broken(i + 1, "record before the start of a run".to_string())),
+                }
+            }
+        }
+    }
+    skip_cut(&mut cut);
+    skip_empty(&mut runs);
+    Ok(runs.pop())
+}
+
+/// Folders of the path which don't exist, parents are the first.
+pub(crate) fn missing_folders(path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|folder| !folder.as_os_str().is_empty() && !folder.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+pub(crate) fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+// Paths are absolute, so the journal doesn't depend on the current dir
+pub(crate) fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Record {
+    pub(crate) fn mk_dir(path: &Path) -> Record {
+        Record::MkDir { path: absolute(path) }
+    }
+
+    pub(crate) fn transfer(from: &Path, to: &Path, mode: TransferMode, overwrite: bool) -> Record {
+        Record::Transfer {
+            from: absolute(from),
+            to: absolute(to),
+            mode,
+            overwrite,
+        }
+    }
+
+    pub(crate) fn rm_file(path: &Path, copy_of: &Path) -> Record {
+        Record::RmFile {
+            path: absolute(path),
+            copy_of: absolute(copy_of),
+        }
+    }
+
+    pub(crate) fn rm_dir(path: &Path) -> Record {
+        Record::RmDir { path: absolute(path) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::test_dir;
+
+    #[test]
+    fn records_are_read_back() {
+        let records = [
+            Record::Run {
+                time: "2022-10-02T10:15:00+03:00".to_string(),
+            },
+            Record::MkDir {
+                path: PathBuf::from("/photos/2022-10-02"),
+            },
+            Record::Transfer {
+                from: PathBuf::from("/card/IMG \"1\"\\.JPG"),
+                to: PathBuf::from("/photos/2022-10-02/Пушкин\t.JPG"),
+                mode: TransferMode::CopyVerifyDelete,
+                overwrite: true,
+            },
+            Record::RmFile {
+                path: PathBuf::from("/card/IMG_2.JPG"),
+                copy_of: PathBuf::from("/photos/2022-10-02/IMG_2.JPG"),
+            },
+            Record::RmDir {
+                path: PathBuf::from("/card/DCIM"),
+            },
+        ];
+        for record in records {
+            let json = serde_json::to_string(&record).unwrap();
+            assert_eq!(from_json(&json).unwrap(), record);
+        }
+    }
+
+    fn from_json(line: &str) -> Result<Record, serde_json::Error> {
+        serde_json::from_str(line)
+    }
+
+    #[test]
+    fn escapes_of_other_writers() {
+        let record = from_json(r#" { "op" : "mkdir", "path": "\/photos\/é📷" } "#).unwrap();
+        assert_eq!(
+            record,
+            Record::MkDir {
+                path: PathBuf::from("/photos/é📷")
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_records() {
+        assert!(from_json(r#"{"op":"mkdir"}"#).is_err());
+        assert!(from_json(r#"{"op":"copy","path":"a"}"#).is_err());
+        assert!(from_json(r#"{"op":"mkdir","path":"a""#).is_err());
+        assert!(from_json(r#"{"op":"mkdir","path":"a"} x"#).is_err());
+        assert!(from_json(r#"{"op":"transfer","mode":"teleport","from":"a","to":"b","overwrite":false}"#).is_err());
+    }
+
+    #[test]
+    fn undone_runs_are_skipped() {
+        let dir = test_dir("journal", "undone_runs_are_skipped");
+
+        let first = Record::mk_dir(Path::new("/photos/first"));
+        let second = Record::mk_dir(Path::new("/photos/second"));
+        {
+            let journal = Journal::start(&dir).unwrap();
+            journal.write(&first).unwrap();
+            let journal = Journal::start(&dir).unwrap();
+            journal.write(&second).unwrap();
+        }
+        assert_eq!(last_run(&dir).unwrap(), Some(vec![second]));
+
+        let journal = Journal::open(&dir).unwrap();
+        journal.write(&Record::Undo { time: now() }).unwrap();
+        assert_eq!(last_run(&dir).unwrap(), Some(vec![first]));
+        journal.write(&Record::Undo { time: now() }).unwrap();
+        assert_eq!(last_run(&dir).unwrap(), None);
+    }
+
+    #[test]
+    fn runs_without_commands_are_skipped() {
+        let dir = test_dir("journal", "runs_without_commands_are_skipped");
+
+        let first = Record::mk_dir(Path::new("/photos/first"));
+        {
+            let journal = Journal::start(&dir).unwrap();
+            journal.write(&first).unwrap();
+            // the run isn't journaled
+            Journal::start(&dir).unwrap().sync().unwrap();
+            // the run of older versions
+            Journal::open(&dir).unwrap().write(&Record::Run { time: now() }).unwrap();
+        }
+        let content = fs::read_to_string(dir.join(JOURNAL_NAME)).unwrap();
+        assert_eq!(content.lines().count(), 3);
+        assert_eq!(last_run(&dir).unwrap(), Some(vec![first]));
+
+        Journal::open(&dir).unwrap().write(&Record::Undo { time: now() }).unwrap();
+        assert_eq!(last_run(&dir).unwrap(), None);
+    }
+
+    #[test]
+    fn cut_records_are_skipped() {
+        let dir = test_dir("journal", "cut_records_are_skipped");
+
+        let first = Record::mk_dir(Path::new("/photos/first"));
+        let second = Record::mk_dir(Path::new("/photos/second"));
+        let journal = Journal::start(&dir).unwrap();
+        journal.write(&first).unwrap();
+        let mut file = OpenOptions::new().append(true).open(dir.join(JOURNAL_NAME)).unwrap();
+        file.write_all(br#"{"op":"mkdir","pa"#).unwrap();
+        assert_eq!(last_run(&dir).unwrap(), Some(vec![first.clone()]));
+
+        // the next run starts at the new line
+        let journal = Journal::start(&dir).unwrap();
+        journal.write(&second).unwrap();
+        assert_eq!(last_run(&dir).unwrap(), Some(vec![second]));
+        journal.write(&Record::Undo { time: now() }).unwrap();
+        assert_eq!(last_run(&dir).unwrap(), Some(vec![first]));
+    }
+
+    #[test]
+    fn broken_records_are_errors() {
+        let dir = test_dir("journal", "broken_records_are_errors");
+
+        // records of the run follow the broken one
+        let journal = Journal::start(&dir).unwrap();
+        journal.write(&Record::mk_dir(Path::new("/photos/first"))).unwrap();
+        let mut file = OpenOptions::new().append(true).open(dir.join(JOURNAL_NAME)).unwrap();
+        file.write_all(b"{\"op\":\"mkdir\"}\n").unwrap();
+        journal.write(&Record::mk_dir(Path::new("/photos/second"))).unwrap();
+
+        let error = last_run(&dir).unwrap_err();
+        assert!(matches!(&error, Error::JournalError { path, line: 3, .. } if *path == dir.join(JOURNAL_NAME)));
+        assert!(error.to_string().starts_with(&format!("journal {}, line 3: ", dir.join(JOURNAL_NAME).to_string_lossy())));
+    }
+}
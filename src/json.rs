@@ -0,0 +1,101 @@
+// Fields of journals, plans and caches which serde doesn't write as they are needed
+
+/// Paths are strings if they are UTF-8. Other paths are arrays of their bytes on unix and
+/// aren't written on other systems, so files are never mistaken for others.
+pub(crate) mod path {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Utf8(String),
+        Bytes(Vec<u8>),
+    }
+
+    pub(crate) fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(path) = path.to_str() {
+            return serializer.serialize_str(path);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            serializer.collect_seq(path.as_os_str().as_bytes())
+        }
+        #[cfg(not(unix))]
+        Err(<S::Error as serde::ser::Error>::custom(format!(
+            "path {} isn't valid UTF-8",
+            path.to_string_lossy()
+        )))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        match Encoded::deserialize(deserializer)? {
+            Encoded::Utf8(path) => Ok(PathBuf::from(path)),
+            #[cfg(unix)]
+            Encoded::Bytes(bytes) => {
+                use std::os::unix::ffi::OsStringExt;
+                Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+            }
+            #[cfg(not(unix))]
+            Encoded::Bytes(_) => Err(<D::Error as serde::de::Error>::custom("path isn't valid UTF-8")),
+        }
+    }
+}
+
+/// Values which are written as their names, like transfer modes.
+pub(crate) mod name {
+    use std::{fmt::Display, str::FromStr};
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub(crate) fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr<Err = String>,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct File {
+        #[serde(with = "super::path")]
+        path: PathBuf,
+    }
+
+    #[test]
+    fn utf8_paths_are_strings() {
+        let file = File {
+            path: PathBuf::from("/photos/Пушкин \"1\"\t.JPG"),
+        };
+        let json = serde_json::to_string(&file).unwrap();
+        assert_eq!(json, r#"{"path":"/photos/Пушкин \"1\"\t.JPG"}"#);
+        assert_eq!(serde_json::from_str::<File>(&json).unwrap(), file);
+        assert!(serde_json::from_str::<File>(r#"{"path":1}"#).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn other_paths_are_bytes() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let file = File {
+            path: PathBuf::from(OsStr::from_bytes(b"/photos/\xff.JPG")),
+        };
+        let json = serde_json::to_string(&file).unwrap();
+        assert_eq!(json, r#"{"path":[47,112,104,111,116,111,115,47,255,46,74,80,71]}"#);
+        assert_eq!(serde_json::from_str::<File>(&json).unwrap(), file);
+    }
+}
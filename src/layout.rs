@@ -0,0 +1,416 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+
+use crate::FileType;
+
+/// Folders of dates with optional descriptions and subfolders for RAWs and videos, like
+/// `2022-10-02 (Pushkin)/raw`.
+pub const DEFAULT_LAYOUT: &str = "{date} {event}/{raw?}/{video?}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// `2022`
+    Year,
+    /// `10`
+    Month,
+    /// `02`
+    Day,
+    /// `2022-10-02`
+    Date,
+    /// Any text of existing folder, like `(Pushkin)`. It's empty for new folders.
+    Event,
+    /// EXIF `Model`
+    Camera,
+    /// `image`, `raw` or `video`
+    Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    /// Folder which name is made by the template, like `{date} {event}`
+    Name { parts: Vec<Part>, pattern: Regex },
+    /// `{raw?}` or `{video?}` - the subfolder for RAWs or videos, it's omitted for other files
+    /// and if RAWs or videos aren't separated
+    Subfolder(FileType),
+}
+
+/// Layout of folders in the work dir, it's a template of the path like
+/// `{year}/{year}-{month}/{date} {event}/{raw?}`. The template is used to make new folders and
+/// to recognize existing ones.
+///
+/// Fields of the template:
+/// * `{year}`, `{month}`, `{day}`, `{date}` - date of the file, `2022`, `10`, `02`, `2022-10-02`;
+/// * `{event}` - any text of existing folder, like `(Pushkin)`, it's empty for new folders;
+/// * `{camera}` - EXIF `Model` of the file;
+/// * `{type}` - `image`, `raw` or `video`;
+/// * `{raw?}`, `{video?}` - subfolders for RAWs and videos, they are omitted for other files.
+///
+/// Text around `{event}` is optional, so the folder `2022-10-02` is matched by
+/// `{date} ({event})` as well as `2022-10-02 (Pushkin)`.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+/// What is known about a file to make its folder.
+#[derive(Debug)]
+pub(crate) struct FileValues<'a> {
+    pub date: NaiveDate,
+    pub camera: Option<&'a str>,
+    pub file_type: FileType,
+    /// Name of the subfolder for `{raw?}` or `{video?}`, None if the file's type isn't separated
+    pub subfolder: Option<&'a str>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        DEFAULT_LAYOUT.parse().unwrap()
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments = s
+            .split('/')
+            .map(parse_segment)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid layout '{}': {}", s, e))?;
+
+        Ok(Layout {
+            template: s.to_string(),
+            segments,
+        })
+    }
+}
+
+fn parse_segment(segment: &str) -> Result<Segment, String> {
+    match segment {
+        "{raw?}" => return Ok(Segment::Subfolder(FileType::RAW)),
+        "{video?}" => return Ok(Segment::Subfolder(FileType::MOVIE)),
+        "" | "." | ".." => return Err(format!("folder '{}' is not allowed", segment)),
+        _ => {}
+    }
+
+    let mut parts = Vec::new();
+    let mut rest = segment;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("'{{' is not closed in '{}'", segment))?;
+        let field = match &rest[start + 1..start + end] {
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            "date" => Field::Date,
+            "event" => Field::Event,
+            "camera" => Field::Camera,
+            "type" => Field::Type,
+            "raw?" | "video?" => return Err(format!("{} should be a whole folder", &rest[start..=start + end])),
+            name => return Err(format!("unknown field {{{}}}", name)),
+        };
+        parts.push(Part::Field(field));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.to_string()));
+    }
+
+    let pattern = Regex::new(&to_pattern(&parts)).map_err(|e| e.to_string())?;
+    Ok(Segment::Name { parts, pattern })
+}
+
+// Each field is a group of the pattern, text next to the event is optional
+fn to_pattern(parts: &[Part]) -> String {
+    let mut pattern = "^".to_string();
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Text(text) if is_next_to_event(parts, i) => {
+                pattern.push_str(&format!("(?:{})?", regex::escape(text)))
+            }
+            Part::Text(text) => pattern.push_str(&regex::escape(text)),
+            Part::Field(field) => pattern.push_str(match field {
+                Field::Year => r"(\d{4})",
+                Field::Month => r"(0[1-9]|1[0-2])",
+                Field::Day => r"(0[1-9]|[12]\d|3[01])",
+                Field::Date => r"(\d{4}-\d{2}-\d{2})",
+                Field::Event => r"(.*?)",
+                Field::Camera => r"(.+?)",
+                Field::Type => r"(image|raw|video)",
+            }),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+fn is_next_to_event(parts: &[Part], i: usize) -> bool {
+    let is_event = |i: usize| parts.get(i) == Some(&Part::Field(Field::Event));
+    is_event(i + 1) || i > 0 && is_event(i - 1)
+}
+
+// Make the name by values of fields, the event and text next to it are omitted
+fn render(parts: &[Part], mut value: impl FnMut(usize, Field) -> Option<String>) -> Option<String> {
+    let mut name = String::new();
+    let mut field_index = 0;
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Text(_) if is_next_to_event(parts, i) => {}
+            Part::Text(text) => name.push_str(text),
+            Part::Field(Field::Event) => field_index += 1,
+            Part::Field(field) => {
+                name.push_str(&value(field_index, *field)?);
+                field_index += 1;
+            }
+        }
+    }
+    Some(name.trim().to_string())
+}
+
+impl Layout {
+    /// Number of folders in the template.
+    pub(crate) fn depth(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Type of files of the `{raw?}` or `{video?}` folder at the level.
+    pub(crate) fn subfolder_at(&self, level: usize) -> Option<FileType> {
+        match self.segments.get(level) {
+            Some(Segment::Subfolder(file_type)) => Some(*file_type),
+            _ => None,
+        }
+    }
+
+    /// Is the folder at the level a folder of files, i.e. there are only `{raw?}` and `{video?}`
+    /// below it.
+    pub(crate) fn is_last(&self, level: usize) -> bool {
+        self.segments[level + 1..]
+            .iter()
+            .all(|segment| matches!(segment, Segment::Subfolder(_)))
+    }
+
+    /// Match the existing folder at the level by the template. Return the folder's name
+    /// without the event, which is the name of the new folder for the same files, like
+    /// `2022-10-02` for `2022-10-02 (Pushkin)`.
+    ///
+    /// `{raw?}` and `{video?}` folders are matched by [Layout::subfolder_at].
+    pub(crate) fn match_folder(&self, level: usize, name: &str) -> Option<String> {
+        let (parts, pattern) = match self.segments.get(level) {
+            Some(Segment::Name { parts, pattern }) => (parts, pattern),
+            _ => return None,
+        };
+
+        let captures = pattern.captures(name)?;
+        let mut is_valid = true;
+        let key = render(parts, |i, field| {
+            let value = captures.get(i + 1).map(|m| m.as_str().to_string());
+            if field == Field::Date {
+                // the pattern doesn't check days of months
+                is_valid &= value.as_ref().is_some_and(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").is_ok());
+            }
+            value
+        })?;
+        is_valid.then_some(key)
+    }
+
+    /// Names of the file's folders from the work dir, without events, like `["2022", "2022-10-02"]`.
+    /// Omitted `{raw?}` and `{video?}` and empty folders are skipped.
+    pub(crate) fn folders_of(&self, values: &FileValues) -> Vec<String> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Name { parts, .. } => render(parts, |_, field| Some(values.value_of(field))),
+                Segment::Subfolder(file_type) if *file_type == values.file_type => {
+                    values.subfolder.map(|name| name.to_string())
+                }
+                Segment::Subfolder(_) => None,
+            })
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    /// Level of folders of days, like `{date} {event}` or `{day}`. Return None if folders of
+    /// days are in folders of cameras or types, they can't be found by a date only.
+    pub(crate) fn date_level(&self) -> Option<usize> {
+        for (level, segment) in self.segments.iter().enumerate() {
+            let parts = match segment {
+                Segment::Name { parts, .. } => parts,
+                Segment::Subfolder(_) => return None,
+            };
+            let has = |field: Field| parts.contains(&Part::Field(field));
+            if has(Field::Camera) || has(Field::Type) {
+                return None;
+            }
+            if has(Field::Date) || has(Field::Day) {
+                return Some(level);
+            }
+        }
+        None
+    }
+
+    /// Names of folders from the work dir to the folder of the day, without events. Return None
+    /// if there are no folders of days, see [Layout::date_level].
+    pub(crate) fn date_folders(&self, date: NaiveDate) -> Option<Vec<String>> {
+        let level = self.date_level()?;
+        let values = FileValues {
+            date,
+            camera: None,
+            file_type: FileType::IMAGE,
+            subfolder: None,
+        };
+        let layout = Layout {
+            template: self.template.clone(),
+            segments: self.segments[..=level].to_vec(),
+        };
+        Some(layout.folders_of(&values))
+    }
+}
+
+/// Camera's model as a name of a file or a folder.
+pub(crate) fn camera_name(camera: Option<&str>) -> String {
+    // a model may have a slash, like "Canon EOS 5D Mark II/III"
+    camera
+        .map(|camera| camera.replace(['/', '\\'], "_").trim().to_string())
+        .filter(|camera| !camera.is_empty())
+        .unwrap_or_else(|| "Unknown camera".to_string())
+}
+
+impl FileValues<'_> {
+    fn value_of(&self, field: Field) -> String {
+        match field {
+            Field::Year => format!("{:04}", self.date.year()),
+            Field::Month => format!("{:02}", self.date.month()),
+            Field::Day => format!("{:02}", self.date.day()),
+            Field::Date => self.date.format("%Y-%m-%d").to_string(),
+            Field::Event => String::new(),
+            Field::Camera => camera_name(self.camera),
+            Field::Type => match self.file_type {
+                FileType::RAW => "raw",
+                FileType::MOVIE => "video",
+                _ => "image",
+            }
+            .to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(file_type: FileType, subfolder: Option<&str>) -> FileValues<'_> {
+        FileValues {
+            date: NaiveDate::from_ymd_opt(2022, 10, 2).unwrap(),
+            camera: Some("PENTAX K-5"),
+            file_type,
+            subfolder,
+        }
+    }
+
+    fn parse(template: &str) -> Layout {
+        template.parse().unwrap()
+    }
+
+    #[test]
+    fn default_layout() {
+        let layout = Layout::default();
+        assert_eq!(layout.folders_of(&values(FileType::IMAGE, None)), vec!["2022-10-02"]);
+        assert_eq!(
+            layout.folders_of(&values(FileType::RAW, Some("raw"))),
+            vec!["2022-10-02", "raw"]
+        );
+        assert_eq!(layout.folders_of(&values(FileType::MOVIE, None)), vec!["2022-10-02"]);
+
+        assert_eq!(layout.match_folder(0, "2022-10-02 (Pushkin)"), Some("2022-10-02".to_string()));
+        assert_eq!(layout.match_folder(0, "2022-10-02"), Some("2022-10-02".to_string()));
+        assert_eq!(layout.match_folder(0, "IMGP1011"), None);
+        assert_eq!(layout.match_folder(0, "2022-02-30"), None);
+        assert!(layout.is_last(0));
+        assert_eq!(layout.date_level(), Some(0));
+        assert_eq!(layout.subfolder_at(1), Some(FileType::RAW));
+        assert_eq!(layout.subfolder_at(2), Some(FileType::MOVIE));
+    }
+
+    #[test]
+    fn hierarchy() {
+        let layout = parse("{year}/{year}-{month}/{date} {event}/{raw?}");
+        assert_eq!(
+            layout.folders_of(&values(FileType::RAW, Some("raw"))),
+            vec!["2022", "2022-10", "2022-10-02", "raw"]
+        );
+        assert_eq!(layout.match_folder(0, "2022"), Some("2022".to_string()));
+        assert!(!layout.is_last(1));
+        assert_eq!(layout.date_level(), Some(2));
+        assert_eq!(
+            layout.date_folders(NaiveDate::from_ymd_opt(2022, 10, 2).unwrap()),
+            Some(vec!["2022".to_string(), "2022-10".to_string(), "2022-10-02".to_string()])
+        );
+        assert_eq!(layout.match_folder(1, "2022-10"), Some("2022-10".to_string()));
+        assert_eq!(layout.match_folder(1, "2022-13"), None);
+        assert_eq!(layout.match_folder(1, "2022-10 October"), None);
+    }
+
+    #[test]
+    fn camera_and_type() {
+        let layout = parse("{camera}/{type}/{month}.{day}");
+        assert_eq!(
+            layout.folders_of(&values(FileType::MOVIE, None)),
+            vec!["PENTAX K-5", "video", "10.02"]
+        );
+        let slashed = FileValues {
+            camera: Some("Canon EOS 5D Mark II/III"),
+            ..values(FileType::IMAGE, None)
+        };
+        assert_eq!(
+            layout.folders_of(&slashed),
+            vec!["Canon EOS 5D Mark II_III", "image", "10.02"]
+        );
+        assert_eq!(layout.match_folder(1, "raw"), Some("raw".to_string()));
+        assert_eq!(layout.date_level(), None);
+        assert_eq!(layout.match_folder(1, "photos"), None);
+    }
+
+    #[test]
+    fn text_around_event() {
+        let layout = parse("{event} - {date}/{year}");
+        assert_eq!(layout.folders_of(&values(FileType::IMAGE, None)), vec!["2022-10-02", "2022"]);
+        assert_eq!(layout.match_folder(0, "Pushkin - 2022-10-02"), Some("2022-10-02".to_string()));
+
+        let layout = parse("{date} ({event})");
+        assert_eq!(layout.match_folder(0, "2022-10-02 (Pushkin)"), Some("2022-10-02".to_string()));
+        assert_eq!(layout.match_folder(0, "2022-10-02"), Some("2022-10-02".to_string()));
+
+        // a folder of events only is omitted in new paths
+        let layout = parse("{year}/{event}/{date}");
+        assert_eq!(layout.folders_of(&values(FileType::IMAGE, None)), vec!["2022", "2022-10-02"]);
+        assert_eq!(layout.match_folder(1, "Trip"), Some("".to_string()));
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!("{date}/{unknown}".parse::<Layout>().is_err());
+        assert!("{date".parse::<Layout>().is_err());
+        assert!("{date}//{raw?}".parse::<Layout>().is_err());
+        assert!("{date}/../{raw?}".parse::<Layout>().is_err());
+        assert!("{date} {raw?}".parse::<Layout>().is_err());
+    }
+}
@@ -1,27 +1,70 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod cache;
+mod clockcorrection;
+mod conflicts;
 mod error;
 mod exifreader;
 mod filesearch;
 mod iocommands;
+mod journal;
+mod json;
+mod layout;
+mod plan;
 mod progress;
+mod recognizers;
+mod renaming;
+mod report;
+mod sidecars;
+mod sniffing;
+#[cfg(test)]
+mod testdir;
+
+pub use crate::clockcorrection::ClockCorrections;
+pub use crate::conflicts::{ConflictPolicy, Resolution};
+pub use crate::error::{DateErrorKind, Error, Operation};
+pub use crate::exifreader::DateSource;
+pub use crate::filesearch::SymlinkPolicy;
+pub use crate::iocommands::TransferMode;
+pub use crate::layout::{Layout, DEFAULT_LAYOUT};
+pub use crate::recognizers::{
+    default_recognizers, recognizer, CompactDate, DateHierarchy, DateRange, DottedDate, FolderRecognizer, IsoDate,
+    Recognized,
+};
+pub use crate::renaming::RenameTemplate;
+pub use crate::report::{ArrangeReport, Conflict, Failure, FailurePolicy, Timings, Transferred, UndoReport};
+pub use crate::sidecars::SidecarNaming;
 
 use std::{
+    collections::{HashMap, HashSet},
+    fmt,
     fs::DirEntry,
     io,
     path::{Path, PathBuf},
-    rc::Rc,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
 };
 
 use crate::{
-    error::Error,
+    cache::{CachedReader, MetadataCache, CACHE_NAME},
+    conflicts::{claim_target, Claimed},
+    error::Context,
     exifreader::{create_exif_reader, ExifData, ExifReader},
-    filesearch::{find_folders, TargetType},
+    filesearch::{find_folders, find_sources},
     iocommands::*,
+    journal::{last_run, missing_folders, now, Journal, Record, JOURNAL_NAME},
+    plan::Plan,
+    layout::FileValues,
+    renaming::{with_suffix, NewNames, RenamedFile},
+    sidecars::{group_sidecars, DEFAULT_SIDECAR_EXTENSIONS},
+    sniffing::{sniff_file, Magic},
 };
 
+use chrono::{FixedOffset, NaiveDate, NaiveTime};
 use dashmap::DashMap;
 use rayon::prelude::*;
 use tracing::{debug, debug_span, info, span, trace, warn, Level};
@@ -31,27 +74,53 @@ pub enum FileType {
     IMAGE,
     RAW,
     MOVIE,
+    /// Not a media file, like `Thumbs.db` or `notes.txt`. It's ignored.
+    OTHER,
 }
 
 impl FileType {
     /**
-     * Detect type of file - image, raw, movie.
+     * Detect type of file by its extension - image, raw, movie.
      *
-     * Return None if path does not contain extension.
+     * Return None if path does not contain extension or the extension is unknown.
      */
-    fn try_from_path<P: AsRef<Path>>(path: P, raw_exts: &Vec<String>) -> Option<FileType> {
-        path.as_ref().extension().map(|ext| {
+    fn try_from_path<P: AsRef<Path>>(path: P, raw_exts: &[String], movie_exts: &[String]) -> Option<FileType> {
+        path.as_ref().extension().and_then(|ext| {
             let ext = ext.to_ascii_lowercase();
             if ext == "jpg" {
                 // the most often case
-                FileType::IMAGE
-            } else if raw_exts.contains(&ext.to_string_lossy().to_string()) {
-                FileType::RAW
+                Some(FileType::IMAGE)
             } else {
-                FileType::IMAGE
+                let ext = ext.to_string_lossy().to_string();
+                if raw_exts.contains(&ext) {
+                    Some(FileType::RAW)
+                } else if movie_exts.contains(&ext) {
+                    Some(FileType::MOVIE)
+                } else if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+                    Some(FileType::IMAGE)
+                } else {
+                    None
+                }
             }
         })
     }
+
+    /**
+     * Detect type of file by its content, the extension is a hint for formats shared by images
+     * and RAWs (JPEG or TIFF with RAW's extension is a RAW) and for formats which are not
+     * recognized by content.
+     */
+    fn detect<P: AsRef<Path>>(path: P, raw_exts: &[String], movie_exts: &[String]) -> FileType {
+        let by_extension = FileType::try_from_path(&path, raw_exts, movie_exts);
+        match (sniff_file(&path), by_extension) {
+            (Some(Magic::Image | Magic::Tiff), Some(FileType::RAW)) => FileType::RAW,
+            (Some(Magic::Image | Magic::Tiff), _) => FileType::IMAGE,
+            (Some(Magic::Raw), _) => FileType::RAW,
+            (Some(Magic::Movie), _) => FileType::MOVIE,
+            (None, Some(file_type @ (FileType::RAW | FileType::MOVIE))) => file_type,
+            (None, _) => FileType::OTHER,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -59,66 +128,124 @@ struct FileInfo {
     path: PathBuf,
     exif: ExifData,
     f_type: FileType,
+    /// Sidecars are moved to the same folder as the file
+    sidecars: Vec<PathBuf>,
 }
 
 #[derive(Debug)]
 pub struct Manager {
     work_dir: PathBuf,
-
-    #[allow(dead_code)]
-    use_exiftool: bool,
+    import_from: Vec<PathBuf>,
+    transfer_mode: TransferMode,
+    conflict_policy: ConflictPolicy,
+    failure_policy: FailurePolicy,
+    layout: Layout,
+    recognizers: Vec<Box<dyn FolderRecognizer>>,
+    rename: Option<RenameTemplate>,
+    exiftool: Option<PathBuf>,
+    date_sources: Vec<DateSource>,
+    time_zone: Option<FixedOffset>,
+    day_starts_at: NaiveTime,
+    clock_corrections: ClockCorrections,
     separate_raw: bool,
     raw_folder: String,
+    separate_video: bool,
+    video_folder: String,
     dry_run: bool,
+    /// Metadata of files is cached in the work dir
+    use_cache: bool,
     raw_exts: Vec<String>,
+    movie_exts: Vec<String>,
+    sidecar_exts: Vec<String>,
+    sidecar_naming: SidecarNaming,
+    max_depth: Option<usize>,
+    symlinks: SymlinkPolicy,
 }
 
 struct FileProcessing {
-    move_file: Option<MoveFile>,
+    move_file: Option<TransferFile>,
     mk_dir: Option<MkDir>,
+    move_sidecars: Vec<TransferFile>,
+    /// The file is a duplicate of the existing one
+    remove_file: Option<RmFile>,
+    /// How the conflict with the existing file is resolved
+    conflict: Option<Conflict>,
 }
 
 impl FileProcessing {
     const EMPTY_FILE_COMMANDS: FileProcessing = FileProcessing {
         move_file: None,
         mk_dir: None,
+        move_sidecars: Vec::new(),
+        remove_file: None,
+        conflict: None,
     };
 
     fn new_empty() -> FileProcessing {
         FileProcessing::EMPTY_FILE_COMMANDS
     }
 
-    fn new(move_file: MoveFile, possible_mk_dir: Option<MkDir>) -> FileProcessing {
+    fn new(move_file: TransferFile, possible_mk_dir: Option<MkDir>) -> FileProcessing {
         FileProcessing {
             move_file: Some(move_file),
             mk_dir: possible_mk_dir,
+            move_sidecars: Vec::new(),
+            remove_file: None,
+            conflict: None,
         }
     }
-
-    fn is_empty(&self) -> bool {
-        self.move_file.is_none()
-    }
 }
 
 // https://fileinfo.com/filetypes/camera_raw
-const RAW_EXTENSIONS: &'static str = include_str!("../resources/raw_extensions");
+const RAW_EXTENSIONS: &str = include_str!("../resources/raw_extensions");
+const MOVIE_EXTENSIONS: &str = include_str!("../resources/movie_extensions");
+const IMAGE_EXTENSIONS: [&str; 9] = ["jpg", "jpeg", "png", "webp", "heic", "heif", "avif", "tif", "tiff"];
 
 unsafe impl Sync for Manager {}
 
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Manager {
     pub fn new() -> Manager {
         let raw_exts = RAW_EXTENSIONS
             .lines()
             .map(|l| l.to_ascii_lowercase().to_string())
             .collect();
+        let movie_exts = MOVIE_EXTENSIONS
+            .lines()
+            .map(|l| l.to_ascii_lowercase().to_string())
+            .collect();
 
         Manager {
             work_dir: PathBuf::from("."),
-            use_exiftool: false,
+            import_from: Vec::new(),
+            transfer_mode: TransferMode::Move,
+            conflict_policy: ConflictPolicy::Skip,
+            failure_policy: FailurePolicy::Continue,
+            layout: Layout::default(),
+            recognizers: default_recognizers(),
+            rename: None,
+            exiftool: None,
+            date_sources: DateSource::DEFAULT_ORDER.to_vec(),
+            time_zone: None,
+            day_starts_at: NaiveTime::MIN,
+            clock_corrections: ClockCorrections::default(),
             separate_raw: true,
             raw_folder: "raw".to_string(),
+            separate_video: false,
+            video_folder: "video".to_string(),
             raw_exts,
+            movie_exts,
+            sidecar_exts: DEFAULT_SIDECAR_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+            sidecar_naming: SidecarNaming::Both,
+            max_depth: None,
+            symlinks: SymlinkPolicy::Skip,
             dry_run: false,
+            use_cache: true,
         }
     }
 
@@ -129,6 +256,62 @@ impl Manager {
         }
     }
 
+    /// Import files from the folders (a memory card, a phone's dump) into the work dir, which is
+    /// the library. Existing folders of dates in the library are reused, other folders of the
+    /// library are not touched.
+    pub fn import_from(self, sources: Vec<PathBuf>) -> Manager {
+        Manager {
+            import_from: sources,
+            ..self
+        }
+    }
+
+    /// Set how files are transferred to their folders, by default they are moved.
+    pub fn transfer_mode(self, mode: TransferMode) -> Manager {
+        Manager {
+            transfer_mode: mode,
+            ..self
+        }
+    }
+
+    /// Set what to do if the target folder already has a file with the same name, by default
+    /// the file is skipped.
+    pub fn conflict_policy(self, policy: ConflictPolicy) -> Manager {
+        Manager {
+            conflict_policy: policy,
+            ..self
+        }
+    }
+
+    /// Set what to do when a command fails or a file can't be read, by default other commands
+    /// are executed.
+    pub fn failure_policy(self, policy: FailurePolicy) -> Manager {
+        Manager {
+            failure_policy: policy,
+            ..self
+        }
+    }
+
+    /// Set the layout of folders, see [Layout]. By default it's [DEFAULT_LAYOUT].
+    pub fn layout(self, layout: Layout) -> Manager {
+        Manager { layout, ..self }
+    }
+
+    /// Set recognizers of existing folders of dates, which don't match the layout's template,
+    /// like `20221002_Pushkin` or `2022-10-01..2022-10-05 Trip`. By default they are
+    /// [default_recognizers], empty list disables recognition.
+    pub fn folder_recognizers(self, recognizers: Vec<Box<dyn FolderRecognizer>>) -> Manager {
+        Manager { recognizers, ..self }
+    }
+
+    /// Rename files by the template, see [RenameTemplate]. By default names are kept.
+    pub fn rename(self, template: RenameTemplate) -> Manager {
+        Manager {
+            rename: Some(template),
+            ..self
+        }
+    }
+
     pub fn dry_run(self) -> Manager {
         Manager {
             dry_run: true,
@@ -136,6 +319,15 @@ impl Manager {
         }
     }
 
+    /// Read metadata of all files, don't use the cache `.photoman-cache.jsonl` in the work dir.
+    /// By default unchanged files aren't read again.
+    pub fn no_cache(self) -> Manager {
+        Manager {
+            use_cache: false,
+            ..self
+        }
+    }
+
     pub fn dont_separate_raw(self) -> Manager {
         Manager {
             separate_raw: false,
@@ -143,89 +335,612 @@ impl Manager {
         }
     }
 
+    /// Place videos in the `video` subfolder of the date's folder, like RAW files in `raw`.
+    pub fn separate_video(self) -> Manager {
+        Manager {
+            separate_video: true,
+            ..self
+        }
+    }
+
+    /// Scan folders up to `depth` levels below the work dir, `1` is the folders in the work
+    /// dir only. By default nested folders are scanned at any depth. Files in the work dir
+    /// are always arranged.
+    pub fn max_depth(self, depth: usize) -> Manager {
+        Manager {
+            max_depth: Some(depth),
+            ..self
+        }
+    }
+
+    /// Set what to do with symbolic links, by default they are skipped.
+    pub fn symlinks(self, policy: SymlinkPolicy) -> Manager {
+        Manager {
+            symlinks: policy,
+            ..self
+        }
+    }
+
+    /// Set extensions of sidecars, by default they are `xmp`, `aae` and `thm`. Sidecars are
+    /// moved together with the file they belong to. Empty list disables sidecars.
+    pub fn sidecar_extensions(self, extensions: Vec<String>) -> Manager {
+        Manager {
+            sidecar_exts: extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_ascii_lowercase())
+                .filter(|e| !e.is_empty())
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Set how names of sidecars are made, see [SidecarNaming].
+    pub fn sidecar_naming(self, naming: SidecarNaming) -> Manager {
+        Manager {
+            sidecar_naming: naming,
+            ..self
+        }
+    }
+
+    /// Read metadata with exiftool instead of the built-in reader. It's slower, but
+    /// supports much more formats (CR3, MKV, proprietary RAWs).
+    pub fn use_exiftool<P: AsRef<Path>>(self, exiftool_path: P) -> Manager {
+        Manager {
+            exiftool: Some(exiftool_path.as_ref().to_path_buf()),
+            ..self
+        }
+    }
+
+    /// Set sources of file's date in order of priority. A file is dated by the first
+    /// source which has the date, see [DateSource::DEFAULT_ORDER].
+    pub fn date_sources(self, sources: Vec<DateSource>) -> Manager {
+        Manager {
+            date_sources: sources,
+            ..self
+        }
+    }
+
+    /// Compute dates of files in the time zone. It's applied to files with known camera's
+    /// time zone only, other files are dated by the camera's clock.
+    pub fn time_zone(self, zone: FixedOffset) -> Manager {
+        Manager {
+            time_zone: Some(zone),
+            ..self
+        }
+    }
+
+    /// Files taken before this time are placed in the folder of the previous day. For example,
+    /// if a day starts at 04:00, a night shooting of 21 june from 18:00 to 03:00 is placed
+    /// in one folder of 21 june.
+    pub fn day_starts_at(self, time: NaiveTime) -> Manager {
+        Manager {
+            day_starts_at: time,
+            ..self
+        }
+    }
+
+    /// Shift time of files by corrections of their cameras' clocks.
+    pub fn clock_corrections(self, corrections: ClockCorrections) -> Manager {
+        Manager {
+            clock_corrections: corrections,
+            ..self
+        }
+    }
+
+    /// Arrange files and return the report of what is done. Files which can't be read or
+    /// transferred are listed by the report, the error is returned if nothing can be done, like
+    /// when the work dir can't be read.
     #[tracing::instrument(skip(self), level=Level::DEBUG)]
-    pub fn arrange_files(&mut self) {
+    pub fn arrange_files(&mut self) -> Result<ArrangeReport, Error> {
         tracing::debug!(?self);
 
-        let exif_reader = create_exif_reader();
+        let cache = self.use_cache.then(|| MetadataCache::open(&self.work_dir));
+        let (plan, mut report) = self.make_plan(cache.as_ref())?;
+        self.execute(&plan, &mut report)?;
+        self.save_cache(cache, &report);
+
+        info!(
+            "Transferred ({}) {} of {} files, ignored {} files which are not images or videos",
+            self.transfer_mode,
+            report.transferred.len(),
+            plan.transfers.len(),
+            report.ignored.len()
+        );
+        if !report.conflicts.is_empty() {
+            info!(
+                "{} files exist in their folders ({}): {} skipped, {} identical, {} renamed, {} overwritten",
+                report.conflicts.len(),
+                self.conflict_policy,
+                report.conflicts_resolved(Resolution::Skipped),
+                report.conflicts_resolved(Resolution::Identical),
+                report.conflicts_resolved(Resolution::Renamed),
+                report.conflicts_resolved(Resolution::Overwritten)
+            );
+        }
+        Ok(report)
+    }
+
+    /// Write the plan of the arrangement to the file instead of executing it. The plan is JSON
+    /// with steps (folders to create, files to transfer, duplicates and folders to remove)
+    /// and skipped files with reasons. It may be edited and executed by [Manager::apply].
+    /// The report lists conflicts and skipped files, nothing is transferred.
+    #[tracing::instrument(skip(self), level=Level::DEBUG)]
+    pub fn plan<P: AsRef<Path> + fmt::Debug>(&mut self, plan_file: P) -> Result<ArrangeReport, Error> {
+        let cache = self.use_cache.then(|| MetadataCache::open(&self.work_dir));
+        let (mut plan, report) = self.make_plan(cache.as_ref())?;
+        self.save_cache(cache, &report);
+        plan.skipped = report.skipped();
+        plan.write(plan_file.as_ref())?;
+        info!(
+            "Planned to create {} folders and transfer ({}) {} files, {} files are skipped, see {}",
+            plan.mk_dirs.len(),
+            self.transfer_mode,
+            plan.transfers.len(),
+            plan.skipped.len(),
+            plan_file.as_ref().to_string_lossy()
+        );
+        if !report.conflicts.is_empty() {
+            info!("{} files exist in their folders ({})", report.conflicts.len(), self.conflict_policy);
+        }
+        Ok(report)
+    }
+
+    /// Execute the plan written by [Manager::plan]. A step is skipped if its source is changed
+    /// since planning, it's a failure of the report. The run is journaled in the work dir of
+    /// the plan.
+    #[tracing::instrument(skip(self), level=Level::DEBUG)]
+    pub fn apply<P: AsRef<Path> + fmt::Debug>(&self, plan_file: P) -> Result<ArrangeReport, Error> {
+        let plan = Plan::read(plan_file.as_ref())?;
+        let mut report = ArrangeReport::default();
+        self.execute(&plan, &mut report)?;
+        self.save_cache(self.use_cache.then(|| MetadataCache::open(&plan.work_dir)), &report);
+        info!(
+            "Transferred {} of {} files of the plan",
+            report.transferred.len(),
+            plan.transfers.len()
+        );
+        Ok(report)
+    }
+
+    // Find sources and targets and make commands
+    fn make_plan(&mut self, cache: Option<&MetadataCache>) -> Result<(Plan, ArrangeReport), Error> {
+        let reader = create_exif_reader(self.exiftool.as_deref())?;
+        let exif_reader = CachedReader {
+            kind: reader.kind(),
+            reader,
+            cache,
+        };
+        let mut report = ArrangeReport::default();
 
         // !!! PERFORMANCE: find_folders
+        let started = Instant::now();
         let span = span!(Level::DEBUG, "find_folders").entered();
-        let folders = find_folders(&self.work_dir, &self.raw_folder).unwrap();
-        span.exit();
+        // in import mode only folders of dates are taken from the library
+        let library_depth = if self.import_from.is_empty() { self.max_depth } else { Some(0) };
+        let folders = find_folders(
+            &self.work_dir,
+            &self.layout,
+            &self.recognizers,
+            &self.raw_folder,
+            &self.video_folder,
+            library_depth,
+            self.symlinks,
+        )
+        .context(Operation::ReadFolder, &self.work_dir)?;
 
-        let sources = folders.source;
+        // files in the roots are arranged too, but the roots are never removed
+        let (roots, sources) = if self.import_from.is_empty() {
+            (vec![self.work_dir.clone()], folders.source)
+        } else {
+            let roots: Vec<PathBuf> = self
+                .import_from
+                .iter()
+                .filter(|root| {
+                    let is_dir = root.is_dir();
+                    if !is_dir {
+                        warn!("Can't import from {}, it's not a folder", root.to_string_lossy());
+                    }
+                    is_dir
+                })
+                .cloned()
+                .collect();
+            let sources = find_sources(&roots, self.max_depth, self.symlinks);
+            (roots, sources)
+        };
+        span.exit();
+        report.timings.find_folders = started.elapsed();
 
         let targets_per_date = Arc::new(folders.target);
 
-        let mut mkdir_commands = Vec::<MkDir>::new();
-        let mut move_commands = Vec::<MoveFile>::new();
+        let mut plan = Plan {
+            work_dir: self.work_dir.clone(),
+            ..Plan::default()
+        };
+        // targets of files, they are unique in the run
+        let claimed = Claimed::new();
 
         // !!! PERFORMANCE: make commands
+        let started = Instant::now();
         let span = debug_span!("make_commands").entered();
         let progress_indicator =
-            progress::ProgressIndicator::new(sources.len(), "read metadata from folders".to_string());
+            progress::ProgressIndicator::new(roots.len() + sources.len(), "read metadata from folders".to_string());
 
-        for source in sources.iter() {
-            let process_result =
-                self.prepare_commands_for_folder(&source, &targets_per_date, &exif_reader);
+        for source in roots.iter().chain(&sources) {
+            let process_result = self.prepare_commands_for_folder(
+                source,
+                &targets_per_date,
+                &claimed,
+                &exif_reader,
+                &mut report,
+            );
             match process_result {
                 Ok(source_commands) => {
                     for sc in source_commands {
-                        mkdir_commands.extend(sc.mk_dir); // implicity unlift option
-                        move_commands.extend(sc.move_file);
+                        plan.mk_dirs.extend(sc.mk_dir); // implicity unlift option
+                        plan.transfers.extend(sc.move_file);
+                        plan.transfers.extend(sc.move_sidecars);
+                        plan.removals.extend(sc.remove_file);
+                        report.conflicts.extend(sc.conflict);
                     }
                 }
-                Err(e) => warn!(
-                    "can't process [{}], error: {}]",
-                    source.to_string_lossy(),
-                    e
-                ),
+                Err(e) => {
+                    warn!("{:#}", e);
+                    report.unreadable.push(Failure {
+                        path: source.clone(),
+                        error: e,
+                    });
+                }
             };
 
             progress_indicator.step();
         }
 
         span.exit();
+        report.timings.read_metadata = started.elapsed();
 
-        debug!("will create {} dirs", mkdir_commands.len());
-        debug!("will move {} images", move_commands.len());
+        // children are removed before their parents, a linked folder isn't ours
+        plan.rm_dirs = sources
+            .iter()
+            .rev()
+            .filter(|source| !source.is_symlink())
+            .map(|source| RmEmptyDir {
+                target: source.to_path_buf(),
+            })
+            .collect();
+
+        debug!("will create {} dirs", plan.mk_dirs.len());
+        debug!("will move {} images", plan.transfers.len());
+        Ok((plan, report))
+    }
+
+    // Write the cache with new paths of transferred files, the cache isn't changed by a dry run
+    fn save_cache(&self, cache: Option<MetadataCache>, report: &ArrangeReport) {
+        let Some(cache) = cache.filter(|_| !self.dry_run) else {
+            return;
+        };
+        cache.transferred(&report.transferred);
+        if let Err(e) = cache.save() {
+            warn!("Can't write the cache of metadata, error: {:#}", e);
+        }
+    }
+
+    // Execute commands of the plan, what is done is added to the report. Failures stop the run
+    // by the failure policy.
+    fn execute(&self, plan: &Plan, report: &mut ArrangeReport) -> Result<(), Error> {
+        let policy = self.failure_policy;
+        if policy == FailurePolicy::AbortBeforeExecute {
+            // sources are checked before anything is changed
+            for move_file in &plan.transfers {
+                if let Err(reason) = plan.check_source(&move_file.from) {
+                    report.failures.push(Failure {
+                        path: move_file.from.clone(),
+                        error: Error::PlanError(reason),
+                    });
+                }
+            }
+            if report.has_failures() {
+                warn!(
+                    "Nothing is executed, {} files can't be read or are changed ({})",
+                    report.unreadable.len() + report.failures.len(),
+                    policy
+                );
+                report.stopped = true;
+                return Ok(());
+            }
+        }
+
+        // executed commands are journaled, so the run can be undone
+        let journal = match self.dry_run {
+            true => None,
+            false => Some(Journal::start(&plan.work_dir)?),
+        };
+        let journal_record = |record: Record| {
+            if let Some(journal) = &journal {
+                journal.record(record);
+            }
+        };
 
         // !!! PERFORMANCE: make directories
+        let started = Instant::now();
         let span = debug_span!("mkdir").entered();
-        for mkdir in mkdir_commands {
-            mkdir.exec(self.dry_run).unwrap()
+        // files aren't transferred into folders which failed to be created
+        let mut failed_folders = Vec::<PathBuf>::new();
+        for mkdir in &plan.mk_dirs {
+            let created = missing_folders(&mkdir.target);
+            match mkdir.exec(self.dry_run) {
+                Ok(_) => {
+                    created.iter().for_each(|folder| journal_record(Record::mk_dir(folder)));
+                    // in a dry run parents of folders are missing for each of them
+                    for folder in created {
+                        if !report.created_folders.contains(&folder) {
+                            report.created_folders.push(folder);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("{:#}", e);
+                    failed_folders.push(mkdir.target.clone());
+                    report.failures.push(Failure {
+                        path: mkdir.target.clone(),
+                        error: e,
+                    });
+                    if policy.stops() {
+                        report.stopped = true;
+                        break;
+                    }
+                }
+            }
         }
         span.exit();
+        report.timings.create_folders = started.elapsed();
 
         // !!! PERFORMANCE: move files
-        let total_images_move = move_commands.len();
+        let total_images_move = plan.transfers.len();
         let progress_indicator =
             progress::ProgressIndicator::new(total_images_move, "moving images ".to_string());
+        let started = Instant::now();
         let span = debug_span!("move images").entered();
 
-        // lv try par
-        move_commands.par_iter().for_each(|move_file| {
-            move_file.exec(self.dry_run).unwrap();
-            progress_indicator.step();
-        });
+        // transfers which are started before the failure are finished
+        let stop = AtomicBool::new(report.stopped);
+        let results: Vec<Option<Result<Transferred, Failure>>> = plan
+            .transfers
+            .par_iter()
+            .map(|move_file| {
+                if stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let result = self.transfer(plan, move_file, &failed_folders, &journal_record);
+                if result.is_err() && policy.stops() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                progress_indicator.step();
+                Some(result)
+            })
+            .collect();
+        // duplicates of files which aren't transferred are kept, their copies are missing
+        let not_transferred: HashSet<&Path> = plan
+            .transfers
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| !matches!(result, Some(Ok(_))))
+            .map(|(move_file, _)| move_file.to.as_path())
+            .collect();
+        for result in results.into_iter().flatten() {
+            match result {
+                Ok(transferred) => report.transferred.push(transferred),
+                Err(failure) => report.failures.push(failure),
+            }
+        }
+        report.stopped = stop.into_inner();
 
         span.exit();
+        report.timings.transfer = started.elapsed();
 
-        for source in &sources {
-            let cmd = RmEmptyDir {
-                target: source.to_path_buf(),
+        // a dry run doesn't transfer, copies are still at their sources
+        let sources: HashMap<&Path, &Path> = match self.dry_run {
+            true => plan.transfers.iter().map(|move_file| (move_file.to.as_path(), move_file.from.as_path())).collect(),
+            false => HashMap::new(),
+        };
+        let started = Instant::now();
+        for remove_file in &plan.removals {
+            if report.stopped {
+                break;
+            }
+            let copy = &remove_file.duplicate_of;
+            let result = if not_transferred.contains(&copy.as_path()) {
+                Err(Error::NotTransferred(copy.clone()))
+            } else {
+                plan.check_duplicate(remove_file, sources.get(copy.as_path()).copied().unwrap_or(copy))
+                    .map_err(Error::PlanError)
+                    .and_then(|_| remove_file.exec(self.dry_run))
             };
-            
-               
+            match result {
+                Ok(_) => journal_record(Record::rm_file(&remove_file.target, &remove_file.duplicate_of)),
+                Err(e) => {
+                    warn!("Can't remove duplicate, {:#}", e);
+                    report.failures.push(Failure {
+                        path: remove_file.target.clone(),
+                        error: e,
+                    });
+                    report.stopped = policy.stops();
+                }
+            }
+        }
 
+        for cmd in &plan.rm_dirs {
+            if report.stopped {
+                break;
+            }
+            let source = &cmd.target;
             match cmd.exec(self.dry_run) {
-                Ok(_) => info!("Removed empty folder {}", source.to_string_lossy()),
-                Err(e) => warn!("Can't remove folder {}, error {}", source.to_string_lossy(),  e),
+                Ok(_) => {
+                    journal_record(Record::rm_dir(source));
+                    info!("Removed empty folder {}", source.to_string_lossy());
+                    report.removed_folders.push(source.clone());
+                }
+                Err(e) if e.io_error_kind() == Some(io::ErrorKind::DirectoryNotEmpty) => {
+                    info!("Folder {} is not empty, it's kept", source.to_string_lossy())
+                }
+                Err(e) => {
+                    warn!("{:#}", e);
+                    report.failures.push(Failure {
+                        path: source.clone(),
+                        error: e,
+                    });
+                    report.stopped = policy.stops();
+                }
+            }
+        }
+
+        if let Some(Err(e)) = journal.as_ref().map(Journal::sync) {
+            warn!("Can't write the journal, error: {}", e);
+        }
+        report.timings.clean_up = started.elapsed();
+        if report.stopped {
+            warn!("The run is stopped after the failure ({}), files are partly arranged", policy);
+        }
+        Ok(())
+    }
+
+    // Transfer the file of the plan unless its source is changed or its folder isn't created
+    fn transfer(
+        &self,
+        plan: &Plan,
+        move_file: &TransferFile,
+        failed_folders: &[PathBuf],
+        journal_record: &(impl Fn(Record) + Sync),
+    ) -> Result<Transferred, Failure> {
+        let fail = |error: Error| Failure {
+            path: move_file.from.clone(),
+            error,
+        };
+        if let Some(folder) = failed_folders.iter().find(|folder| move_file.to.starts_with(folder)) {
+            warn!("{} isn't transferred, {} isn't created", move_file.from.to_string_lossy(), folder.to_string_lossy());
+            return Err(fail(Error::FolderNotCreated(folder.clone())));
+        }
+        if let Err(reason) = plan.check_source(&move_file.from) {
+            warn!("{} isn't transferred, {}", move_file.from.to_string_lossy(), reason);
+            return Err(fail(Error::PlanError(reason)));
+        }
+        match move_file.exec(self.dry_run) {
+            Ok(_) => {
+                journal_record(Record::transfer(
+                    &move_file.from,
+                    &move_file.to,
+                    move_file.mode,
+                    move_file.overwrite,
+                ));
+                Ok(Transferred {
+                    from: move_file.from.clone(),
+                    to: move_file.to.clone(),
+                    mode: move_file.mode,
+                })
+            }
+            Err(e) => {
+                warn!("{:#}", e);
+                Err(fail(e))
             }
         }
     }
 
+    /// Revert the last run in the work dir by its journal: moved files are moved back, copies
+    /// and created folders are removed, removed source folders are created again. Each call
+    /// reverts the previous run. Commands which are already reverted are skipped, so the undo
+    /// can be repeated if some commands have failed.
+    #[tracing::instrument(skip(self), level=Level::DEBUG)]
+    pub fn undo(&self) -> Result<UndoReport, Error> {
+        let Some(records) = last_run(&self.work_dir)? else {
+            info!("Nothing to undo in {}, all runs are undone", self.work_dir.to_string_lossy());
+            return Ok(UndoReport::default());
+        };
+
+        let mut report = UndoReport {
+            commands: records.len(),
+            failures: Vec::new(),
+        };
+        for record in records.iter().rev() {
+            let (path, result) = match record {
+                Record::Transfer {
+                    from,
+                    to,
+                    mode,
+                    overwrite,
+                } => {
+                    if *overwrite {
+                        warn!("{} replaced the existing file, it can't be restored", to.to_string_lossy());
+                    }
+                    let result = if !to.exists() && from.exists() {
+                        Ok(()) // reverted by the previous undo
+                    } else if mode.removes_source() || !from.exists() {
+                        let move_back = TransferFile {
+                            from: to.clone(),
+                            to: from.clone(),
+                            mode: TransferMode::Move,
+                            overwrite: false,
+                        };
+                        match from.parent().filter(|folder| !folder.exists()) {
+                            Some(folder) => MkDir {
+                                target: folder.to_path_buf(),
+                            }
+                            .exec(self.dry_run)
+                            .and_then(|_| move_back.exec(self.dry_run)),
+                            None => move_back.exec(self.dry_run),
+                        }
+                    } else {
+                        // the copy edited after the run is kept
+                        match same_content(to, from) {
+                            Ok(true) => RmFile {
+                                target: to.clone(),
+                                duplicate_of: from.clone(),
+                            }
+                            .exec(self.dry_run),
+                            Ok(false) => Err(io::Error::other("the copy is changed after the run"))
+                                .context(Operation::RemoveFile, to),
+                            Err(e) => Err(e).context(Operation::RemoveFile, to),
+                        }
+                    };
+                    (to, result)
+                }
+                Record::RmFile { path, .. } if path.exists() => (path, Ok(())),
+                Record::RmFile { path, copy_of } => {
+                    let copy_back = TransferFile {
+                        from: copy_of.clone(),
+                        to: path.clone(),
+                        mode: TransferMode::Copy,
+                        overwrite: false,
+                    };
+                    (path, copy_back.exec(self.dry_run))
+                }
+                Record::RmDir { path } => (path, MkDir { target: path.clone() }.exec(self.dry_run)),
+                Record::MkDir { path } if !path.exists() => (path, Ok(())),
+                Record::MkDir { path } => (path, RmEmptyDir { target: path.clone() }.exec(self.dry_run)),
+                Record::Run { .. } | Record::Undo { .. } => continue, // they aren't in runs
+            };
+            if let Err(e) = result {
+                warn!("Can't undo {:?}, error: {:#}", record, e);
+                report.failures.push(Failure {
+                    path: path.clone(),
+                    error: e,
+                });
+            }
+        }
+
+        if report.has_failures() {
+            warn!("The run isn't marked as undone, undo can be repeated after failures are fixed");
+        } else if !self.dry_run {
+            let journal = Journal::open(&self.work_dir)?;
+            journal.write(&Record::Undo { time: now() })?;
+            journal.sync()?;
+        }
+        info!(
+            "Undone {} of {} commands of the last run",
+            report.commands - report.failures.len(),
+            report.commands
+        );
+        Ok(report)
+    }
+
     // show warning and return None
     fn warn_io_error<T, S, P>(operation: S, e: Error, path: P) -> Option<T>
     where
@@ -246,7 +961,7 @@ impl Manager {
                 )
             }
             _ => warn!(
-                "{}, path [{}], e = {}",
+                "{}, path [{}], e = {:#}",
                 operation.as_ref(),
                 path.as_ref().to_string_lossy(),
                 e
@@ -258,20 +973,22 @@ impl Manager {
     #[tracing::instrument(skip_all, level=Level::TRACE )]
     fn prepare_commands_for_folder(
         &mut self,
-        source_folder: &PathBuf,
-        targets_per_date: &Arc<DashMap<TargetType, PathBuf>>,
+        source_folder: &Path,
+        targets_per_date: &Arc<DashMap<PathBuf, PathBuf>>,
+        claimed: &Claimed,
         exif_reader: &impl ExifReader,
+        report: &mut ArrangeReport,
     ) -> Result<Vec<FileProcessing>, Error> {
-        let dir_name = source_folder.as_path().to_string_lossy().to_string();
+        let dir_name = source_folder.to_string_lossy().to_string();
         trace!(
             "read EXIF from images in {}",
-            source_folder.as_path().to_string_lossy()
+            source_folder.to_string_lossy()
         );
 
         let span = debug_span!("getting list of files", folder = dir_name).entered();
         let mut files_in_folder = Vec::<DirEntry>::new();
-        for _entry in source_folder.read_dir()? {
-            files_in_folder.push(_entry?);
+        for _entry in source_folder.read_dir().context(Operation::ReadFolder, source_folder)? {
+            files_in_folder.push(_entry.context(Operation::ReadFolder, source_folder)?);
         }
         span.exit();
 
@@ -280,9 +997,10 @@ impl Manager {
             format!("read from images in folder {}: ", dir_name),
         );
 
-        let x = files_in_folder
+        let paths: Vec<PathBuf> = files_in_folder
             .par_iter()
-            .filter_map(|dir_entry| match dir_entry.metadata() {
+            .filter(|dir_entry| dir_entry.file_name() != JOURNAL_NAME && dir_entry.file_name() != CACHE_NAME)
+            .filter_map(|dir_entry| match self.symlinks.metadata(dir_entry) {
                 Ok(metadata) => {
                     if metadata.is_file() {
                         Some(dir_entry)
@@ -295,27 +1013,75 @@ impl Manager {
                     Self::warn_io_error("Can't read metadata", Error::Io(e), dir_entry.path())
                 }
             })
-            .filter_map(|dir_entry| {
-                FileType::try_from_path(dir_entry.path(), &self.raw_exts)
-                    .map(|file_type| (dir_entry, file_type))
+            .map(|dir_entry| dir_entry.path())
+            .collect();
+
+        // sidecars have no metadata, they are moved with their files
+        let (sidecars, paths): (Vec<_>, Vec<_>) = paths.into_iter().partition(|path| self.is_sidecar(path));
+        let files: Vec<(PathBuf, FileType)> = paths
+            .into_par_iter()
+            .map(|path| {
+                let file_type = FileType::detect(&path, &self.raw_exts, &self.movie_exts);
+                (path, file_type)
+            })
+            .collect();
+
+        let (others, files): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|(_, file_type)| *file_type == FileType::OTHER);
+        let (mut sidecars_per_file, orphans) =
+            group_sidecars(&files, sidecars, self.sidecar_naming);
+
+        for (path, _) in &others {
+            debug!("{} is not an image or video, ignored", path.to_string_lossy());
+        }
+        for path in &orphans {
+            debug!("{} is a sidecar without its file, ignored", path.to_string_lossy());
+        }
+        report.ignored.extend(others.into_iter().map(|(path, _)| path));
+        report.ignored.extend(orphans);
+
+        let paths: Vec<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+        let exifs = exif_reader.read_batch(&paths, &self.date_sources);
+
+        let file_infos: Vec<Result<FileInfo, Failure>> = files
+            .into_iter()
+            .map(|(path, file_type)| {
+                let sidecars = sidecars_per_file.remove(&path).unwrap_or_default();
+                (path, file_type, sidecars)
             })
-            .filter_map(
-                |(dir_entry, file_type)| match exif_reader.read(dir_entry.path()) {
-                    Ok(exif_data) => Some(FileInfo {
-                        exif: exif_data,
-                        path: dir_entry.path(),
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .zip(exifs)
+            .map(|((path, file_type, sidecars), exif)| match exif {
+                Ok(exif_data) => {
+                    if exif_data.source != DateSource::DateTimeOriginal {
+                        debug!("{} is dated by {}", path.to_string_lossy(), exif_data.source);
+                    }
+                    Ok(FileInfo {
+                        exif: self.clock_corrections.apply(exif_data),
+                        path,
                         f_type: file_type,
-                    }),
+                        sidecars,
+                    })
+                }
 
-                    Err(e) => Self::warn_io_error("Can't read EXIF", e, dir_entry.path()),
-                },
-            )
-            .filter_map(|file_info| {
-                let r = match self.make_commands_to_process_image(targets_per_date, &file_info) {
-                    Ok(commands) => Some(commands),
-                    Err(e) => Self::warn_io_error("Can't prepare commands", e, file_info.path),
-                };
-                progress_indicator.step();
+                Err(e) => {
+                    warn!("{:#}", e);
+                    Err(Failure { path, error: e })
+                }
+            })
+            .collect();
+        let (file_infos, unreadable): (Vec<_>, Vec<_>) = file_infos.into_iter().partition(Result::is_ok);
+        report.unreadable.extend(unreadable.into_iter().filter_map(Result::err));
+        let file_infos: Vec<FileInfo> = file_infos.into_iter().filter_map(Result::ok).collect();
+
+        let x = self
+            .group_shots(file_infos)
+            .into_par_iter()
+            .flat_map_iter(|shot| {
+                let r = self.make_commands_to_process_shot(targets_per_date, claimed, &shot);
+                shot.iter().for_each(|_| progress_indicator.step());
                 r
             });
 
@@ -325,38 +1091,126 @@ impl Manager {
         return Ok(v);
     }
 
-    // Analyze image and make required commands. One image may require moving file and creating
-    // new directory
-    fn make_commands_to_process_image(
+    fn is_sidecar(&self, path: &Path) -> bool {
+        path.extension()
+            .map(|ext| self.sidecar_exts.contains(&ext.to_string_lossy().to_ascii_lowercase()))
+            .unwrap_or(false)
+    }
+
+    // Date of the folder for the file, a day starts at `self.day_starts_at`
+    fn folder_date(&self, exif: &ExifData) -> NaiveDate {
+        let since_midnight = self.day_starts_at - NaiveTime::MIN;
+        (exif.datetime_in(self.time_zone) - since_midnight).date()
+    }
+
+    // Files which are renamed together: RAW+JPEG with the same name get the same new name.
+    // Without renaming each file is alone.
+    fn group_shots(&self, file_infos: Vec<FileInfo>) -> Vec<Vec<FileInfo>> {
+        if self.rename.is_none() {
+            return file_infos.into_iter().map(|file_info| vec![file_info]).collect();
+        }
+
+        let mut shots = Vec::<Vec<FileInfo>>::new();
+        let mut by_stem = HashMap::<String, usize>::new();
+        for file_info in file_infos {
+            let stem = file_info.path.file_stem().unwrap_or_default().to_string_lossy().to_lowercase();
+            match by_stem.get(&stem) {
+                Some(&i) => shots[i].push(file_info),
+                None => {
+                    by_stem.insert(stem, shots.len());
+                    shots.push(vec![file_info]);
+                }
+            }
+        }
+        shots
+    }
+
+    // Make commands for files of one shot, see [Manager::group_shots]
+    fn make_commands_to_process_shot(
         &self,
-        folder_per_date: &Arc<DashMap<TargetType, PathBuf>>,
-        file_info: &FileInfo,
-    ) -> Result<FileProcessing, Error> {
-        let image_path = &file_info.path;
-        let exif = &file_info.exif;
+        folder_per_date: &Arc<DashMap<PathBuf, PathBuf>>,
+        claimed: &Claimed,
+        shot: &[FileInfo],
+    ) -> Vec<FileProcessing> {
+        let folders: Vec<(PathBuf, Option<MkDir>)> = shot
+            .iter()
+            .map(|file_info| self.target_folder(folder_per_date, file_info))
+            .collect();
 
-        let image_name = match image_path.file_name() {
-            Some(image_name) => image_name,
-            None => return Ok(FileProcessing::new_empty()), // file_name == .. , do nothing
+        let names = match &self.rename {
+            Some(template) => {
+                let files: Vec<RenamedFile> = shot
+                    .iter()
+                    .zip(&folders)
+                    .map(|(file_info, (folder, _))| RenamedFile {
+                        path: &file_info.path,
+                        folder,
+                        sidecars: &file_info.sidecars,
+                    })
+                    .collect();
+                // RAW is the original of the shot
+                let first = shot.iter().find(|f| f.f_type == FileType::RAW).unwrap_or(&shot[0]);
+                template.rename(
+                    &files,
+                    first.exif.datetime_in(self.time_zone),
+                    first.exif.camera.as_deref(),
+                    claimed,
+                )
+            }
+            None => shot
+                .iter()
+                .map(|file_info| NewNames {
+                    file: file_info.path.file_name().unwrap_or_default().to_os_string(),
+                    sidecars: file_info
+                        .sidecars
+                        .iter()
+                        .map(|sidecar| sidecar.file_name().unwrap_or_default().to_os_string())
+                        .collect(),
+                })
+                .collect(),
         };
 
-        let put_in_raw_folder = file_info.f_type == FileType::RAW && self.separate_raw;
+        shot.iter()
+            .zip(folders)
+            .zip(names)
+            .map(|((file_info, (folder, mk_dir)), names)| {
+                self.make_commands_to_process_image(file_info, folder, mk_dir, names, claimed)
+            })
+            .collect()
+    }
 
-        let target_key = if put_in_raw_folder {
-            TargetType::RAW(file_info.exif.date)
-        } else {
-            TargetType::IMAGE(file_info.exif.date)
+    // Folder of the file by the layout, it's created if it doesn't exist
+    fn target_folder(
+        &self,
+        folder_per_date: &Arc<DashMap<PathBuf, PathBuf>>,
+        file_info: &FileInfo,
+    ) -> (PathBuf, Option<MkDir>) {
+        let date = self.folder_date(&file_info.exif);
+
+        let subfolder = match file_info.f_type {
+            FileType::RAW if self.separate_raw => Some(self.raw_folder.as_str()),
+            FileType::MOVIE if self.separate_video => Some(self.video_folder.as_str()),
+            _ => None,
         };
+        let folders = self.layout.folders_of(&FileValues {
+            date,
+            camera: file_info.exif.camera.as_deref(),
+            file_type: file_info.f_type,
+            subfolder,
+        });
+
+        // new folders are created in the deepest existing one, like "2022-10-02 (Pushkin)/raw"
+        let (parent, existing) = (1..=folders.len())
+            .rev()
+            .find_map(|depth| {
+                let key: PathBuf = folders[..depth].iter().collect();
+                folder_per_date.get(&key).map(|dir| (dir.clone(), depth))
+            })
+            .unwrap_or_else(|| (self.work_dir.clone(), 0));
 
         let mut possible_mk_dir: Option<MkDir> = None;
-        let date_dir = folder_per_date.entry(target_key).or_insert_with(|| {
-            let date = exif.date.format("%Y-%m-%d").to_string();
-            let date_folder = self.work_dir.clone().join(date);
-            let target = if put_in_raw_folder {
-                date_folder.join(&self.raw_folder)
-            } else {
-                date_folder
-            };
+        let date_dir = folder_per_date.entry(folders.iter().collect()).or_insert_with(|| {
+            let target: PathBuf = folders[existing..].iter().fold(parent, |dir, name| dir.join(name));
 
             possible_mk_dir = Some(MkDir {
                 target: target.to_path_buf(),
@@ -365,19 +1219,137 @@ impl Manager {
             target
         });
 
-        // === still reoder in folders with dates not supported
-        // === should be implemented by self.move_in_targets or something like
+        (date_dir.clone(), possible_mk_dir)
+    }
 
-        // should not overwrite an existing file
-        let target_filename = date_dir.join(image_name);
-        if !target_filename.exists() {
-            let move_file = MoveFile {
-                from: image_path.to_path_buf(),
-                to: target_filename,
-            };
-            Ok(FileProcessing::new(move_file, possible_mk_dir))
+    // Analyze image and make required commands. One image may require moving file with its
+    // sidecars and creating new directory. If the file exists in the folder or another file of
+    // the run is placed there, the conflict is resolved by the policy.
+    fn make_commands_to_process_image(
+        &self,
+        file_info: &FileInfo,
+        date_dir: PathBuf,
+        possible_mk_dir: Option<MkDir>,
+        names: NewNames,
+        claimed: &Claimed,
+    ) -> FileProcessing {
+        let image_path = &file_info.path;
+
+        let target_filename = date_dir.join(&names.file);
+        // the target is claimed even if it exists, only the first file of the run is compared
+        // with the existing one
+        let claimant = claim_target(claimed, &target_filename, image_path);
+        let in_run = claimant.is_some();
+        let existing = match claimant {
+            Some(claimant) => claimant,
+            None if target_filename.exists() => target_filename.clone(),
+            None => return self.transfer_commands(file_info, &date_dir, possible_mk_dir, names, claimed, false),
+        };
+
+        let resolution = match self.conflict_policy.resolve(image_path, &existing) {
+            // files of the run never replace each other, the order of transfers is unknown
+            Ok(Resolution::Overwritten) if in_run => Resolution::Renamed,
+            Ok(resolution) => resolution,
+            Err(e) => {
+                warn!("Can't compare {} with {}, error: {}", image_path.to_string_lossy(), existing.to_string_lossy(), e);
+                Resolution::Skipped
+            }
+        };
+        if in_run {
+            info!(
+                "{} is placed in {} as {} too, {}",
+                existing.to_string_lossy(),
+                date_dir.to_string_lossy(),
+                names.file.to_string_lossy(),
+                resolution
+            );
         } else {
-            Ok(FileProcessing::new_empty())
+            info!(
+                "{} exists in {}, {}",
+                names.file.to_string_lossy(),
+                date_dir.to_string_lossy(),
+                resolution
+            );
+        }
+
+        let commands = match resolution {
+            Resolution::Skipped => FileProcessing::new_empty(),
+            Resolution::Identical => {
+                // the file is in place, its sidecars are placed unless they exist
+                let FileProcessing { move_sidecars, .. } =
+                    self.transfer_commands(file_info, &date_dir, None, names, claimed, false);
+                // the file of the run is moved away, its copy is the target after transfers
+                FileProcessing {
+                    move_sidecars,
+                    remove_file: self.transfer_mode.removes_source().then(|| RmFile {
+                        target: image_path.to_path_buf(),
+                        duplicate_of: if in_run { target_filename.clone() } else { existing.clone() },
+                    }),
+                    ..FileProcessing::new_empty()
+                }
+            }
+            Resolution::Renamed => {
+                let file = RenamedFile {
+                    path: image_path,
+                    folder: &date_dir,
+                    sidecars: &file_info.sidecars,
+                };
+                let names = with_suffix(&file, &names, claimed);
+                self.transfer_commands(file_info, &date_dir, possible_mk_dir, names, claimed, false)
+            }
+            Resolution::Overwritten => {
+                self.transfer_commands(file_info, &date_dir, possible_mk_dir, names, claimed, true)
+            }
+        };
+        FileProcessing {
+            conflict: Some(Conflict {
+                path: image_path.to_path_buf(),
+                target: target_filename,
+                existing,
+                resolution,
+            }),
+            ..commands
+        }
+    }
+
+    // Transfer the file and its sidecars to the folder, existing sidecars are replaced only
+    // with the file. Sidecars placed by other files of the run are skipped.
+    fn transfer_commands(
+        &self,
+        file_info: &FileInfo,
+        date_dir: &Path,
+        possible_mk_dir: Option<MkDir>,
+        names: NewNames,
+        claimed: &Claimed,
+        overwrite: bool,
+    ) -> FileProcessing {
+        let move_file = TransferFile {
+            from: file_info.path.to_path_buf(),
+            to: date_dir.join(&names.file),
+            mode: self.transfer_mode,
+            overwrite,
+        };
+
+        let move_sidecars = file_info
+            .sidecars
+            .iter()
+            .zip(names.sidecars)
+            .filter_map(|(sidecar, name)| {
+                let target = date_dir.join(name);
+                let exists = target.exists();
+                let taken = claim_target(claimed, &target, sidecar).is_some();
+                (!taken && (overwrite || !exists)).then(|| TransferFile {
+                    from: sidecar.clone(),
+                    to: target,
+                    mode: self.transfer_mode,
+                    overwrite: exists,
+                })
+            })
+            .collect();
+
+        FileProcessing {
+            move_sidecars,
+            ..FileProcessing::new(move_file, possible_mk_dir)
         }
     }
 }
@@ -1,20 +1,124 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use chrono::{FixedOffset, NaiveTime};
+use clap::{Parser, Subcommand};
 
-use photoman::Manager;
-use tracing::info;
+use photoman::{
+    recognizer, ArrangeReport, ClockCorrections, ConflictPolicy, DateSource, FailurePolicy, Layout, Manager,
+    RenameTemplate, Resolution, SidecarNaming, SymlinkPolicy, TransferMode, UndoReport,
+};
+use tracing::error;
 use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter, filter::LevelFilter};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// working directory, default = current directory
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    arrange: ArrangeArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ArrangeArgs {
+    /// working directory, default = current directory. It's the library in import mode
     #[arg(default_value = ".")]
     work_dir: PathBuf,
+    /// import files from the folder into the working directory, may be repeated
+    #[arg(long, value_name = "PATH")]
+    import: Vec<PathBuf>,
+    /// how files are transferred: move (default), copy, copy-verify-delete, hardlink, reflink
+    #[arg(long, value_name = "MODE")]
+    transfer: Option<TransferMode>,
+    /// if the file exists in its folder: skip (default), skip-identical, rename, keep-newer, overwrite
+    #[arg(long, value_name = "POLICY")]
+    conflicts: Option<ConflictPolicy>,
+    /// if a command fails: continue (default), stop-after-current, abort-before-execute
+    #[arg(long, value_name = "POLICY")]
+    on_failure: Option<FailurePolicy>,
+    /// template of folders, default: "{date} {event}/{raw?}/{video?}", see README
+    #[arg(long, value_name = "TEMPLATE")]
+    layout: Option<Layout>,
+    /// recognizers of existing folders: range, iso, compact, dotted, hierarchy (default - all)
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    recognizers: Option<Vec<String>>,
+    /// rename files by the template, like "{datetime}_{camera}_{seq}.{ext}", see README
+    #[arg(long, value_name = "TEMPLATE")]
+    rename: Option<RenameTemplate>,
     /// output command without execution
     #[arg(long, action = clap::ArgAction::SetTrue, default_value="false")]
     dry_run: bool,
+    /// read metadata of all files, don't use the cache of the working directory
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value="false")]
+    no_cache: bool,
+    /// scan nested folders up to DEPTH levels, 1 is the folders in the working directory only
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+    /// symbolic links: skip (default) or follow
+    #[arg(long, value_name = "POLICY")]
+    symlinks: Option<SymlinkPolicy>,
+    /// place videos in the `video` subfolder of the date's folder
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value="false")]
+    separate_video: bool,
+    /// extensions of sidecars which are moved with their files, default: xmp,aae,thm
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',')]
+    sidecars: Option<Vec<String>>,
+    /// how sidecars are named: stem (IMG_1.xmp), fullname (IMG_1.CR2.xmp) or both
+    #[arg(long, value_name = "NAMING")]
+    sidecar_naming: Option<SidecarNaming>,
+    /// read metadata with exiftool, optionally set path to the executable
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "exiftool")]
+    exiftool: Option<PathBuf>,
+    /// sources of file's date in order of priority: original, digitized, datetime, filename, mtime
+    #[arg(long, value_name = "SOURCES", value_delimiter = ',')]
+    date_sources: Option<Vec<DateSource>>,
+    /// compute dates in the time zone, like +03:00 (applied to files with known camera's time zone)
+    #[arg(long, value_name = "OFFSET", allow_hyphen_values = true)]
+    time_zone: Option<FixedOffset>,
+    /// files taken before this time are placed in the folder of the previous day, like 04:00
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    day_starts_at: Option<NaiveTime>,
+    /// file with corrections of cameras' clocks, see README
+    #[arg(long, value_name = "FILE")]
+    clock_corrections: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// write the plan of the arrangement to the file instead of executing it
+    Plan {
+        /// file of the plan, CSV if it has the `csv` extension, otherwise JSON
+        #[arg(long, short, value_name = "FILE", default_value = "plan.json")]
+        output: PathBuf,
+        #[command(flatten)]
+        arrange: Box<ArrangeArgs>,
+    },
+    /// execute the plan, steps with changed sources are skipped
+    Apply {
+        /// file of the plan
+        plan: PathBuf,
+        /// if a command fails: continue (default), stop-after-current, abort-before-execute
+        #[arg(long, value_name = "POLICY")]
+        on_failure: Option<FailurePolicy>,
+        /// output commands without execution
+        #[arg(long, action = clap::ArgAction::SetTrue, default_value="false")]
+        dry_run: bool,
+    },
+    /// revert the last run in the working directory by its journal
+    Undo {
+        /// working directory, default = current directory
+        #[arg(default_value = ".")]
+        work_dir: PathBuf,
+        /// output commands without execution
+        #[arg(long, action = clap::ArgAction::SetTrue, default_value="false")]
+        dry_run: bool,
+    },
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(&format!("{}:00", value), "%H:%M"))
+        .map_err(|_| format!("expected time as HH:MM or HH, got '{}'", value))
 }
 
 fn main() {
@@ -34,11 +138,201 @@ fn main() {
         .with_ansi(true)
         .init();
 
+    match args.command {
+        Some(Command::Undo { work_dir, dry_run }) => {
+            let mut manager = Manager::new().work_dir(work_dir);
+            if dry_run {
+                manager = manager.dry_run();
+            }
+            match manager.undo() {
+                Ok(report) => print_undo_report(&report),
+                Err(e) => {
+                    error!("Can't undo the last run, error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Command::Plan { output, arrange }) => {
+            match manager(*arrange).plan(&output) {
+                Ok(report) => print_report(&report),
+                Err(e) => {
+                    error!("Can't write the plan [{}], error: {:#}", output.to_string_lossy(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Command::Apply {
+            plan,
+            on_failure,
+            dry_run,
+        }) => {
+            let mut manager = Manager::new();
+            if let Some(on_failure) = on_failure {
+                manager = manager.failure_policy(on_failure);
+            }
+            if dry_run {
+                manager = manager.dry_run();
+            }
+            match manager.apply(&plan) {
+                Ok(report) => print_report(&report),
+                Err(e) => {
+                    error!("Can't apply the plan [{}], error: {:#}", plan.to_string_lossy(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            let work_dir = args.arrange.work_dir.clone();
+            match manager(args.arrange).arrange_files() {
+                Ok(report) => print_report(&report),
+                Err(e) => {
+                    error!("Can't arrange files in [{}], error: {:#}", work_dir.to_string_lossy(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+// Print the summary table, exit with 1 if there were failures
+fn print_report(report: &ArrangeReport) {
+    let timings = &report.timings;
+    let rows = [
+        ("transferred", report.transferred.len().to_string()),
+        ("created folders", report.created_folders.len().to_string()),
+        ("removed folders", report.removed_folders.len().to_string()),
+        (
+            "conflicts",
+            format!(
+                "{} ({} skipped, {} identical, {} renamed, {} overwritten)",
+                report.conflicts.len(),
+                report.conflicts_resolved(Resolution::Skipped),
+                report.conflicts_resolved(Resolution::Identical),
+                report.conflicts_resolved(Resolution::Renamed),
+                report.conflicts_resolved(Resolution::Overwritten)
+            ),
+        ),
+        ("ignored", report.ignored.len().to_string()),
+        ("unreadable", report.unreadable.len().to_string()),
+        ("failed", report.failures.len().to_string()),
+        (
+            "time",
+            format!(
+                "{:.2?} (find folders {:.2?}, read metadata {:.2?}, create folders {:.2?}, transfer {:.2?}, clean up {:.2?})",
+                timings.total(),
+                timings.find_folders,
+                timings.read_metadata,
+                timings.create_folders,
+                timings.transfer,
+                timings.clean_up
+            ),
+        ),
+    ];
+    for (name, value) in rows {
+        println!("{:<16} {}", name, value);
+    }
+    for failure in report.unreadable.iter().chain(&report.failures) {
+        println!("  {}: {:#}", failure.path.to_string_lossy(), failure.error);
+    }
+
+    if report.stopped {
+        println!("the run is stopped after the failure, other commands aren't executed");
+    }
+
+    if report.has_failures() {
+        std::process::exit(1);
+    }
+}
+
+// Print reverted commands and failures, exit with 1 if there were failures
+fn print_undo_report(report: &UndoReport) {
+    println!("{:<16} {}", "reverted", report.commands - report.failures.len());
+    println!("{:<16} {}", "failed", report.failures.len());
+    for failure in &report.failures {
+        println!("  {}: {:#}", failure.path.to_string_lossy(), failure.error);
+    }
+
+    if report.has_failures() {
+        println!("the run isn't marked as undone, undo can be repeated after failures are fixed");
+        std::process::exit(1);
+    }
+}
 
+fn manager(args: ArrangeArgs) -> Manager {
     let mut manager = Manager::new().work_dir(args.work_dir);
+    if !args.import.is_empty() {
+        manager = manager.import_from(args.import);
+    }
+    if let Some(transfer) = args.transfer {
+        manager = manager.transfer_mode(transfer);
+    }
+    if let Some(conflicts) = args.conflicts {
+        manager = manager.conflict_policy(conflicts);
+    }
+    if let Some(on_failure) = args.on_failure {
+        manager = manager.failure_policy(on_failure);
+    }
+    if let Some(layout) = args.layout {
+        manager = manager.layout(layout);
+    }
+    if let Some(names) = args.recognizers {
+        let recognizers = names
+            .iter()
+            .filter(|name| !name.is_empty())
+            .map(|name| recognizer(name))
+            .collect::<Result<Vec<_>, _>>();
+        match recognizers {
+            Ok(recognizers) => manager = manager.folder_recognizers(recognizers),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(rename) = args.rename {
+        manager = manager.rename(rename);
+    }
     if args.dry_run {
         manager = manager.dry_run();
     }
-    manager.arrange_files();
-
+    if args.no_cache {
+        manager = manager.no_cache();
+    }
+    if let Some(max_depth) = args.max_depth {
+        manager = manager.max_depth(max_depth);
+    }
+    if let Some(symlinks) = args.symlinks {
+        manager = manager.symlinks(symlinks);
+    }
+    if args.separate_video {
+        manager = manager.separate_video();
+    }
+    if let Some(sidecars) = args.sidecars {
+        manager = manager.sidecar_extensions(sidecars);
+    }
+    if let Some(sidecar_naming) = args.sidecar_naming {
+        manager = manager.sidecar_naming(sidecar_naming);
+    }
+    if let Some(exiftool) = args.exiftool {
+        manager = manager.use_exiftool(exiftool);
+    }
+    if let Some(date_sources) = args.date_sources {
+        manager = manager.date_sources(date_sources);
+    }
+    if let Some(time_zone) = args.time_zone {
+        manager = manager.time_zone(time_zone);
+    }
+    if let Some(day_starts_at) = args.day_starts_at {
+        manager = manager.day_starts_at(day_starts_at);
+    }
+    if let Some(path) = args.clock_corrections {
+        match ClockCorrections::from_file(&path) {
+            Ok(corrections) => manager = manager.clock_corrections(corrections),
+            Err(e) => {
+                error!("Can't read clock corrections [{}], error: {}", path.to_string_lossy(), e);
+                std::process::exit(2);
+            }
+        }
+    }
+    manager
 }
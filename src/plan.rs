@@ -0,0 +1,538 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    iocommands::{same_content, MkDir, RmEmptyDir, RmFile, TransferFile, TransferMode},
+    journal::{absolute, now},
+    json,
+};
+
+/// A file which isn't arranged, with the reason.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Skipped {
+    #[serde(with = "json::path")]
+    pub(crate) path: PathBuf,
+    pub(crate) reason: String,
+}
+
+/// Size and modification time of a source, the source is changed if they differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    pub(crate) size: u64,
+    /// Nanoseconds since the epoch
+    pub(crate) modified: u64,
+}
+
+impl Fingerprint {
+    pub(crate) fn of(path: &Path) -> io::Result<Fingerprint> {
+        Fingerprint::of_metadata(&fs::metadata(path)?)
+    }
+
+    pub(crate) fn of_metadata(metadata: &fs::Metadata) -> io::Result<Fingerprint> {
+        let since_epoch = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Fingerprint {
+            size: metadata.len(),
+            modified: u64::try_from(since_epoch.as_nanos()).unwrap_or(u64::MAX),
+        })
+    }
+}
+
+/// Commands of the arrangement in order of their execution: folders are created, files are
+/// transferred, duplicates are removed and emptied sources are removed.
+#[derive(Debug, Default)]
+pub(crate) struct Plan {
+    /// Folder of the journal
+    pub(crate) work_dir: PathBuf,
+    pub(crate) mk_dirs: Vec<MkDir>,
+    pub(crate) transfers: Vec<TransferFile>,
+    pub(crate) removals: Vec<RmFile>,
+    /// Source folders to remove if they are empty, children are before their parents
+    pub(crate) rm_dirs: Vec<RmEmptyDir>,
+    pub(crate) skipped: Vec<Skipped>,
+    /// Sources as they were planned, None for the plan which isn't read from a file
+    pub(crate) sources: Option<HashMap<PathBuf, Fingerprint>>,
+}
+
+/// Step of the plan file. Size and modification time of sources may be removed by editing,
+/// then sources are only checked to exist.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Step {
+    MkDir {
+        #[serde(with = "json::path")]
+        path: PathBuf,
+    },
+    Transfer {
+        #[serde(with = "json::name")]
+        mode: TransferMode,
+        #[serde(with = "json::path")]
+        from: PathBuf,
+        #[serde(with = "json::path")]
+        to: PathBuf,
+        #[serde(default)]
+        overwrite: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        modified: Option<u64>,
+    },
+    Rm {
+        #[serde(with = "json::path")]
+        path: PathBuf,
+        #[serde(with = "json::path")]
+        copy_of: PathBuf,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        modified: Option<u64>,
+    },
+    RmDir {
+        #[serde(with = "json::path")]
+        path: PathBuf,
+    },
+}
+
+// The plan file as it's written
+#[derive(Serialize)]
+struct PlanJson<'a> {
+    #[serde(with = "json::path")]
+    work_dir: PathBuf,
+    created: String,
+    steps: Vec<Step>,
+    skipped: &'a [Skipped],
+}
+
+// Fields of the plan file which are read, `created` and `skipped` are for the reader
+#[derive(Deserialize)]
+struct PlanFile {
+    #[serde(with = "json::path")]
+    work_dir: PathBuf,
+    /// Steps are read one by one, so errors have their numbers
+    steps: Vec<serde_json::Value>,
+}
+
+// Row of the CSV plan. The first row is `work_dir`, then steps with the fields of their JSON
+// steps, `rm` has the copy in `target`, and `skipped` files with reasons.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Row {
+    op: String,
+    path: PathBuf,
+    target: Option<PathBuf>,
+    mode: Option<String>,
+    overwrite: Option<bool>,
+    size: Option<u64>,
+    modified: Option<u64>,
+    reason: Option<String>,
+}
+
+impl From<Step> for Row {
+    fn from(step: Step) -> Row {
+        match step {
+            Step::MkDir { path } => Row {
+                op: "mkdir".to_string(),
+                path,
+                ..Row::default()
+            },
+            Step::Transfer {
+                mode,
+                from,
+                to,
+                overwrite,
+                size,
+                modified,
+            } => Row {
+                op: "transfer".to_string(),
+                path: from,
+                target: Some(to),
+                mode: Some(mode.to_string()),
+                overwrite: Some(overwrite),
+                size,
+                modified,
+                reason: None,
+            },
+            Step::Rm {
+                path,
+                copy_of,
+                size,
+                modified,
+            } => Row {
+                op: "rm".to_string(),
+                path,
+                target: Some(copy_of),
+                size,
+                modified,
+                ..Row::default()
+            },
+            Step::RmDir { path } => Row {
+                op: "rmdir".to_string(),
+                path,
+                ..Row::default()
+            },
+        }
+    }
+}
+
+impl TryFrom<Row> for Step {
+    type Error = String;
+
+    fn try_from(row: Row) -> Result<Step, String> {
+        let target = |target: Option<PathBuf>, name: &str| target.ok_or(format!("no {} in `target`", name));
+        match row.op.as_str() {
+            "mkdir" => Ok(Step::MkDir { path: row.path }),
+            "transfer" => Ok(Step::Transfer {
+                mode: row.mode.ok_or("no `mode`")?.parse()?,
+                from: row.path,
+                to: target(row.target, "destination")?,
+                overwrite: row.overwrite.unwrap_or(false),
+                size: row.size,
+                modified: row.modified,
+            }),
+            "rm" => Ok(Step::Rm {
+                path: row.path,
+                copy_of: target(row.target, "copy")?,
+                size: row.size,
+                modified: row.modified,
+            }),
+            "rmdir" => Ok(Step::RmDir { path: row.path }),
+            op => Err(format!("unknown op `{}`", op)),
+        }
+    }
+}
+
+/// The plan is CSV if its file has the `csv` extension, otherwise it's JSON.
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+impl Plan {
+    /// Write the plan, one step per line, so it may be reviewed and edited. Paths are absolute.
+    /// The plan is CSV if the file has the `csv` extension, CSV can't have paths which aren't
+    /// valid UTF-8.
+    pub(crate) fn write(&self, path: &Path) -> Result<(), Error> {
+        match is_csv(path) {
+            true => self.write_csv(path),
+            false => self.write_json(path),
+        }
+    }
+
+    fn steps(&self) -> Vec<Step> {
+        // it's checked that the source exists
+        let fingerprint = |source: &Path| match Fingerprint::of(source) {
+            Ok(fingerprint) => (Some(fingerprint.size), Some(fingerprint.modified)),
+            Err(_) => (None, None),
+        };
+
+        let mut steps = Vec::<Step>::new();
+        steps.extend(self.mk_dirs.iter().map(|mk_dir| Step::MkDir {
+            path: absolute(&mk_dir.target),
+        }));
+        steps.extend(self.transfers.iter().map(|transfer| {
+            let (size, modified) = fingerprint(&transfer.from);
+            Step::Transfer {
+                mode: transfer.mode,
+                from: absolute(&transfer.from),
+                to: absolute(&transfer.to),
+                overwrite: transfer.overwrite,
+                size,
+                modified,
+            }
+        }));
+        steps.extend(self.removals.iter().map(|remove| {
+            let (size, modified) = fingerprint(&remove.target);
+            Step::Rm {
+                path: absolute(&remove.target),
+                copy_of: absolute(&remove.duplicate_of),
+                size,
+                modified,
+            }
+        }));
+        steps.extend(self.rm_dirs.iter().map(|rm_dir| Step::RmDir {
+            path: absolute(&rm_dir.target),
+        }));
+        steps
+    }
+
+    fn write_json(&self, path: &Path) -> Result<(), Error> {
+        let plan = PlanJson {
+            work_dir: absolute(&self.work_dir),
+            created: now(),
+            steps: self.steps(),
+            skipped: &self.skipped,
+        };
+        // the file isn't written if a path can't be
+        let mut content = Vec::new();
+        serde_json::to_writer_pretty(&mut content, &plan)?;
+        content.push(b'\n');
+        fs::write(path, content).map_err(Error::from)
+    }
+
+    fn write_csv(&self, path: &Path) -> Result<(), Error> {
+        let work_dir = Row {
+            op: "work_dir".to_string(),
+            path: absolute(&self.work_dir),
+            ..Row::default()
+        };
+        let skipped = self.skipped.iter().map(|skipped| Row {
+            op: "skipped".to_string(),
+            path: skipped.path.clone(),
+            reason: Some(skipped.reason.clone()),
+            ..Row::default()
+        });
+
+        // the file isn't written if a path can't be
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for row in std::iter::once(work_dir)
+            .chain(self.steps().into_iter().map(Row::from))
+            .chain(skipped)
+        {
+            writer
+                .serialize(&row)
+                .map_err(|e| Error::PlanError(format!("{}: {}", row.path.to_string_lossy(), e)))?;
+        }
+        let content = writer.into_inner().map_err(|e| Error::PlanError(e.to_string()))?;
+        fs::write(path, content).map_err(Error::from)
+    }
+
+    /// Read the plan written by [Plan::write], sources are checked by [Plan::check_source].
+    pub(crate) fn read(path: &Path) -> Result<Plan, Error> {
+        let (work_dir, steps) = match is_csv(path) {
+            true => Plan::read_csv(path)?,
+            false => Plan::read_json(path)?,
+        };
+
+        let mut plan = Plan {
+            work_dir,
+            ..Plan::default()
+        };
+        let mut sources = HashMap::new();
+        let mut add_source = |path: &Path, size: Option<u64>, modified: Option<u64>| {
+            if let (Some(size), Some(modified)) = (size, modified) {
+                sources.insert(path.to_path_buf(), Fingerprint { size, modified });
+            }
+        };
+        for step in steps {
+            match step {
+                Step::MkDir { path } => plan.mk_dirs.push(MkDir { target: path }),
+                Step::Transfer {
+                    mode,
+                    from,
+                    to,
+                    overwrite,
+                    size,
+                    modified,
+                } => {
+                    add_source(&from, size, modified);
+                    plan.transfers.push(TransferFile {
+                        from,
+                        to,
+                        mode,
+                        overwrite,
+                    });
+                }
+                Step::Rm {
+                    path,
+                    copy_of,
+                    size,
+                    modified,
+                } => {
+                    add_source(&path, size, modified);
+                    plan.removals.push(RmFile {
+                        target: path,
+                        duplicate_of: copy_of,
+                    });
+                }
+                Step::RmDir { path } => plan.rm_dirs.push(RmEmptyDir { target: path }),
+            }
+        }
+        plan.sources = Some(sources);
+        Ok(plan)
+    }
+
+    fn read_json(path: &Path) -> Result<(PathBuf, Vec<Step>), Error> {
+        let content = fs::read_to_string(path)?;
+        let file: PlanFile = serde_json::from_str(&content).map_err(|e| Error::PlanError(e.to_string()))?;
+        let steps = file
+            .steps
+            .into_iter()
+            .enumerate()
+            .map(|(i, step)| {
+                serde_json::from_value(step).map_err(|e| Error::PlanError(format!("step {}: {}", i + 1, e)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((file.work_dir, steps))
+    }
+
+    fn read_csv(path: &Path) -> Result<(PathBuf, Vec<Step>), Error> {
+        let mut reader = csv::Reader::from_path(path).map_err(|e| Error::PlanError(e.to_string()))?;
+        let mut work_dir = None;
+        let mut steps = Vec::new();
+        for row in reader.deserialize::<Row>() {
+            let row = row.map_err(|e| Error::PlanError(e.to_string()))?;
+            match row.op.as_str() {
+                "work_dir" => work_dir = Some(row.path),
+                "skipped" => {}
+                _ => steps.push(
+                    Step::try_from(row)
+                        .map_err(|e| Error::PlanError(format!("step {}: {}", steps.len() + 1, e)))?,
+                ),
+            }
+        }
+        let work_dir = work_dir.ok_or_else(|| Error::PlanError("no `work_dir` row".to_string()))?;
+        Ok((work_dir, steps))
+    }
+
+    /// Check that the source isn't changed since planning. Sources of the plan which isn't
+    /// read from a file aren't checked.
+    pub(crate) fn check_source(&self, path: &Path) -> Result<(), String> {
+        let Some(sources) = &self.sources else {
+            return Ok(());
+        };
+        let fingerprint = Fingerprint::of(path).map_err(|e| format!("source can't be read: {}", e))?;
+        match sources.get(path) {
+            Some(planned) if *planned != fingerprint => Err("source is changed since planning".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the file is still the duplicate of `copy`, it's the copy of the removal or the
+    /// source of its transfer. Files of the plan which isn't read from a file are compared by the
+    /// conflict policy.
+    pub(crate) fn check_duplicate(&self, remove: &RmFile, copy: &Path) -> Result<(), String> {
+        if self.sources.is_none() {
+            return Ok(());
+        }
+        self.check_source(&remove.target)?;
+        match same_content(&remove.target, copy) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("{} is changed", copy.to_string_lossy())),
+            Err(e) => Err(format!("can't compare with {}: {}", copy.to_string_lossy(), e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::test_dir;
+    use crate::iocommands::TransferMode;
+
+    #[test]
+    fn plan_is_read_back() {
+        let dir = test_dir("plan", "plan_is_read_back");
+        let (source, copy) = (dir.join("01.jpg"), dir.join("01_copy.jpg"));
+        fs::write(&source, "image").unwrap();
+        fs::write(&copy, "image").unwrap();
+
+        let plan = Plan {
+            work_dir: dir.clone(),
+            mk_dirs: vec![MkDir {
+                target: dir.join("2022-10-02"),
+            }],
+            transfers: vec![TransferFile {
+                from: source.clone(),
+                to: dir.join("2022-10-02").join("01.jpg"),
+                mode: TransferMode::CopyVerifyDelete,
+                overwrite: false,
+            }],
+            removals: vec![RmFile {
+                target: copy.clone(),
+                duplicate_of: source.clone(),
+            }],
+            rm_dirs: vec![RmEmptyDir { target: dir.join("DCIM") }],
+            skipped: vec![Skipped {
+                path: dir.join("notes.txt"),
+                reason: "not an image or video".to_string(),
+            }],
+            sources: None,
+        };
+        for file in ["plan.json", "plan.csv"] {
+            plan.write(&dir.join(file)).unwrap();
+
+            let read = Plan::read(&dir.join(file)).unwrap();
+            assert_eq!(read.work_dir, dir);
+            assert_eq!(read.mk_dirs[0].target, plan.mk_dirs[0].target);
+            assert_eq!(read.transfers[0].to, plan.transfers[0].to);
+            assert_eq!(read.transfers[0].mode, TransferMode::CopyVerifyDelete);
+            assert_eq!(read.removals[0].duplicate_of, source);
+            assert_eq!(read.rm_dirs[0].target, dir.join("DCIM"));
+            assert!(read.skipped.is_empty()); // they are for review only
+
+            assert_eq!(read.check_source(&source), Ok(()));
+            assert_eq!(read.check_duplicate(&read.removals[0], &source), Ok(()));
+        }
+        let read = Plan::read(&dir.join("plan.csv")).unwrap();
+        fs::write(&source, "edited image").unwrap();
+        assert!(read.check_source(&source).is_err());
+        assert!(read.check_duplicate(&read.removals[0], &source).is_err());
+    }
+
+    #[test]
+    fn csv_plan() {
+        let dir = test_dir("plan", "csv_plan");
+        let plan = dir.join("plan.csv");
+
+        fs::write(
+            &plan,
+            "op,path,target,mode,overwrite,size,modified,reason\n\
+             work_dir,/photos,,,,,,\n\
+             transfer,\"/import/a, b.jpg\",/photos/a.jpg,copy,,,,\n\
+             skipped,/import/notes.txt,,,,,,not an image or video\n",
+        )
+        .unwrap();
+        let read = Plan::read(&plan).unwrap();
+        assert_eq!(read.work_dir, PathBuf::from("/photos"));
+        assert_eq!(read.transfers[0].from, PathBuf::from("/import/a, b.jpg"));
+        assert_eq!(read.transfers[0].mode, TransferMode::Copy);
+        assert!(!read.transfers[0].overwrite);
+
+        fs::write(&plan, "op,path\nwork_dir,/photos\ncopy,a\n").unwrap();
+        assert!(matches!(Plan::read(&plan), Err(Error::PlanError(message)) if message.starts_with("step 1")));
+        fs::write(&plan, "op,path,target\nwork_dir,/photos,\nrm,a,\n").unwrap();
+        assert!(matches!(Plan::read(&plan), Err(Error::PlanError(message)) if message.starts_with("step 1")));
+        fs::write(&plan, "op,path\nmkdir,a\n").unwrap();
+        assert!(matches!(Plan::read(&plan), Err(Error::PlanError(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn csv_plan_rejects_non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = test_dir("plan", "csv_plan_rejects_non_utf8_paths");
+        let plan = Plan {
+            work_dir: dir.clone(),
+            mk_dirs: vec![MkDir {
+                target: dir.join(OsStr::from_bytes(b"\xff")),
+            }],
+            ..Plan::default()
+        };
+        assert!(matches!(plan.write(&dir.join("plan.csv")), Err(Error::PlanError(_))));
+        assert!(!dir.join("plan.csv").exists());
+
+        plan.write(&dir.join("plan.json")).unwrap();
+        assert_eq!(Plan::read(&dir.join("plan.json")).unwrap().mk_dirs[0].target, plan.mk_dirs[0].target);
+    }
+
+    #[test]
+    fn invalid_plans() {
+        let dir = test_dir("plan", "invalid_plans");
+        let plan = dir.join("plan.json");
+
+        fs::write(&plan, r#"{"work_dir": "/photos", "steps": [{"op": "copy", "path": "a"}]}"#).unwrap();
+        assert!(matches!(Plan::read(&plan), Err(Error::PlanError(message)) if message.starts_with("step 1")));
+        fs::write(&plan, r#"{"steps": []}"#).unwrap();
+        assert!(matches!(Plan::read(&plan), Err(Error::PlanError(_))));
+        fs::write(&plan, r#"{"work_dir": "/photos", "steps": [{"op": "mkdir", "path": "a"}"#).unwrap();
+        assert!(matches!(Plan::read(&plan), Err(Error::PlanError(_))));
+    }
+}
@@ -25,7 +25,7 @@ impl ProgressIndicator {
     }
 
     fn show_progress(&self, step_number: usize) {
-        if step_number % self.threshold == 0 {
+        if step_number.is_multiple_of(self.threshold) {
             let value = if self.is_percent {
                 step_number / self.threshold
             } else {
@@ -0,0 +1,248 @@
+use std::{fmt, path::Path};
+
+use chrono::NaiveDate;
+use regex::{Captures, Regex};
+
+lazy_static! {
+    // 2022-10-02, 2022-10-02 (Pushkin)
+    static ref ISO_DATE: Regex = Regex::new(r"^(\d{4})-(\d{2})-(\d{2})(?:\D.*)?$").unwrap();
+
+    // 20221002, 20221002_Pushkin
+    static ref COMPACT_DATE: Regex = Regex::new(r"^(\d{4})(\d{2})(\d{2})(?:\D.*)?$").unwrap();
+
+    // 2022.10.02, 2022_10_02 Pushkin
+    static ref DOTTED_DATE: Regex = Regex::new(r"^(\d{4})[._](\d{2})[._](\d{2})(?:\D.*)?$").unwrap();
+
+    // 2022-10-01..2022-10-05 Trip, 2022-10-01..05, 2022-10-28 - 11-02
+    static ref DATE_RANGE: Regex = Regex::new(
+        r"^(\d{4})-(\d{2})-(\d{2})(?:\.\.| ?[–—] ?| - )(?:(?:(\d{4})-)?(\d{2})-)?(\d{2})(?:\D.*)?$"
+    )
+    .unwrap();
+
+    // 2022/10/02 Pushkin, 2022/10 October/02
+    static ref YEAR: Regex = Regex::new(r"^(\d{4})$").unwrap();
+    static ref MONTH_OR_DAY: Regex = Regex::new(r"^(\d{2})(?:\D.*)?$").unwrap();
+}
+
+/// Ranges longer than it are not folders of trips, like `2000-01-01..2022-12-31 Archive`.
+const MAX_RANGE_DAYS: i64 = 366;
+
+/// What the folder is by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recognized {
+    /// Folder of files taken in the days, both are included. It's one day for most folders.
+    Days(NaiveDate, NaiveDate),
+    /// Folder may have folders of dates, like `2022` of `2022/10/02`
+    Parent,
+}
+
+/// Recognizes existing folders of dates by their names, so folders of other layouts than the
+/// template are reused instead of being arranged.
+pub trait FolderRecognizer: fmt::Debug + Send + Sync {
+    /// Recognize the folder by its path from the work dir, like `2022/10/02 Pushkin`. Return
+    /// None if the folder isn't known.
+    fn recognize(&self, folder: &Path) -> Option<Recognized>;
+}
+
+/// `2022-10-02`, any text may follow the date: `2022-10-02 (Pushkin)`.
+#[derive(Debug, Clone, Copy)]
+pub struct IsoDate;
+
+/// `20221002`, `20221002_Pushkin`.
+#[derive(Debug, Clone, Copy)]
+pub struct CompactDate;
+
+/// `2022.10.02`, `2022_10_02 Pushkin`.
+#[derive(Debug, Clone, Copy)]
+pub struct DottedDate;
+
+/// Trips of several days: `2022-10-01..2022-10-05 Trip`, `2022-10-01..05`, `2022-10-28 - 11-02`.
+/// Files of each day are placed in the folder, unless there is a folder of the day.
+#[derive(Debug, Clone, Copy)]
+pub struct DateRange;
+
+/// Folders of years, months and days: `2022/10/02 Pushkin`.
+#[derive(Debug, Clone, Copy)]
+pub struct DateHierarchy;
+
+/// Recognizers of all known folders, ranges are before single dates.
+pub fn default_recognizers() -> Vec<Box<dyn FolderRecognizer>> {
+    vec![
+        Box::new(DateRange),
+        Box::new(IsoDate),
+        Box::new(CompactDate),
+        Box::new(DottedDate),
+        Box::new(DateHierarchy),
+    ]
+}
+
+/// Recognizer by its name: `range`, `iso`, `compact`, `dotted` or `hierarchy`.
+pub fn recognizer(name: &str) -> Result<Box<dyn FolderRecognizer>, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "range" => Ok(Box::new(DateRange)),
+        "iso" => Ok(Box::new(IsoDate)),
+        "compact" => Ok(Box::new(CompactDate)),
+        "dotted" => Ok(Box::new(DottedDate)),
+        "hierarchy" => Ok(Box::new(DateHierarchy)),
+        _ => Err(format!(
+            "unknown recognizer '{}', expected one of: range, iso, compact, dotted, hierarchy",
+            name
+        )),
+    }
+}
+
+fn folder_name(folder: &Path) -> Option<&str> {
+    folder.file_name().and_then(|name| name.to_str())
+}
+
+// Date of groups `year`, `month` and `day`, None if it doesn't exist
+fn date_of(c: &Captures, year: usize, month: usize, day: usize) -> Option<NaiveDate> {
+    let number = |i: usize| c[i].parse::<u32>().ok(); // only digits are captured
+    NaiveDate::from_ymd_opt(number(year)? as i32, number(month)?, number(day)?)
+}
+
+fn one_day(regex: &Regex, folder: &Path) -> Option<Recognized> {
+    let c = regex.captures(folder_name(folder)?)?;
+    date_of(&c, 1, 2, 3).map(|date| Recognized::Days(date, date))
+}
+
+impl FolderRecognizer for IsoDate {
+    fn recognize(&self, folder: &Path) -> Option<Recognized> {
+        one_day(&ISO_DATE, folder)
+    }
+}
+
+impl FolderRecognizer for CompactDate {
+    fn recognize(&self, folder: &Path) -> Option<Recognized> {
+        one_day(&COMPACT_DATE, folder)
+    }
+}
+
+impl FolderRecognizer for DottedDate {
+    fn recognize(&self, folder: &Path) -> Option<Recognized> {
+        one_day(&DOTTED_DATE, folder)
+    }
+}
+
+impl FolderRecognizer for DateRange {
+    fn recognize(&self, folder: &Path) -> Option<Recognized> {
+        let c = DATE_RANGE.captures(folder_name(folder)?)?;
+        let first = date_of(&c, 1, 2, 3)?;
+
+        // the year and the month of the end may be omitted
+        let part = |i: usize, of_first: &str| c.get(i).map_or(of_first.to_string(), |m| m.as_str().to_string());
+        let last = NaiveDate::from_ymd_opt(
+            part(4, &c[1]).parse().ok()?,
+            part(5, &c[2]).parse().ok()?,
+            c[6].parse().ok()?,
+        )?;
+
+        let days = (last - first).num_days();
+        (days > 0 && days < MAX_RANGE_DAYS).then_some(Recognized::Days(first, last))
+    }
+}
+
+impl FolderRecognizer for DateHierarchy {
+    fn recognize(&self, folder: &Path) -> Option<Recognized> {
+        let names: Vec<&str> = folder
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<_>>()?;
+
+        // the hierarchy may be in another folder, like `Photos/2022/10/02`
+        let year_at = names.iter().rposition(|name| YEAR.is_match(name))?;
+        let year = names[year_at];
+        match &names[year_at + 1..] {
+            [] => Some(Recognized::Parent),
+            [month] => {
+                let month = MONTH_OR_DAY.captures(month)?;
+                (1..=12).contains(&month[1].parse::<u32>().ok()?).then_some(Recognized::Parent)
+            }
+            [month, day] => {
+                let month = MONTH_OR_DAY.captures(month)?;
+                let day = MONTH_OR_DAY.captures(day)?;
+                let date = NaiveDate::from_ymd_opt(year.parse().ok()?, month[1].parse().ok()?, day[1].parse().ok()?)?;
+                Some(Recognized::Days(date, date))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn day(year: i32, month: u32, d: u32) -> Option<Recognized> {
+        Some(Recognized::Days(date(year, month, d), date(year, month, d)))
+    }
+
+    fn recognize(recognizer: impl FolderRecognizer, folder: &str) -> Option<Recognized> {
+        recognizer.recognize(Path::new(folder))
+    }
+
+    #[test]
+    fn iso_date() {
+        assert_eq!(recognize(IsoDate, "2022-10-02"), day(2022, 10, 2));
+        assert_eq!(recognize(IsoDate, "2022-10-02 (Pushkin)"), day(2022, 10, 2));
+        assert_eq!(recognize(IsoDate, "2022-10-32"), None);
+        assert_eq!(recognize(IsoDate, "2022-10-023"), None);
+    }
+
+    #[test]
+    fn compact_date() {
+        assert_eq!(recognize(CompactDate, "20221002"), day(2022, 10, 2));
+        assert_eq!(recognize(CompactDate, "20221002_Pushkin"), day(2022, 10, 2));
+        assert_eq!(recognize(CompactDate, "20221302"), None);
+        assert_eq!(recognize(CompactDate, "IMGP1011"), None);
+    }
+
+    #[test]
+    fn dotted_date() {
+        assert_eq!(recognize(DottedDate, "2022.10.02"), day(2022, 10, 2));
+        assert_eq!(recognize(DottedDate, "2022_10_02 Pushkin"), day(2022, 10, 2));
+        assert_eq!(recognize(DottedDate, "2022-10-02"), None);
+    }
+
+    #[test]
+    fn date_range() {
+        let trip = Some(Recognized::Days(date(2022, 10, 1), date(2022, 10, 5)));
+        assert_eq!(recognize(DateRange, "2022-10-01..2022-10-05 Trip"), trip);
+        assert_eq!(recognize(DateRange, "2022-10-01..05"), trip);
+        assert_eq!(recognize(DateRange, "2022-10-01 - 10-05 Trip"), trip);
+        assert_eq!(recognize(DateRange, "2022-10-01–2022-10-05"), trip);
+        assert_eq!(
+            recognize(DateRange, "2022-12-28..2023-01-03 New Year"),
+            Some(Recognized::Days(date(2022, 12, 28), date(2023, 1, 3)))
+        );
+
+        // an event, not a range
+        assert_eq!(recognize(DateRange, "2022-10-02 - Pushkin"), None);
+        // the end is before the start or too far
+        assert_eq!(recognize(DateRange, "2022-10-05..01"), None);
+        assert_eq!(recognize(DateRange, "2000-01-01..2022-12-31 Archive"), None);
+    }
+
+    #[test]
+    fn date_hierarchy() {
+        assert_eq!(recognize(DateHierarchy, "2022"), Some(Recognized::Parent));
+        assert_eq!(recognize(DateHierarchy, "2022/10"), Some(Recognized::Parent));
+        assert_eq!(recognize(DateHierarchy, "2022/10 October"), Some(Recognized::Parent));
+        assert_eq!(recognize(DateHierarchy, "2022/10/02 Pushkin"), day(2022, 10, 2));
+        assert_eq!(recognize(DateHierarchy, "Photos/2022/10/02"), day(2022, 10, 2));
+        assert_eq!(recognize(DateHierarchy, "2022/13"), None);
+        assert_eq!(recognize(DateHierarchy, "2022/10/02/raw"), None);
+        assert_eq!(recognize(DateHierarchy, "DCIM"), None);
+    }
+
+    #[test]
+    fn by_name() {
+        assert!(recognizer("range").is_ok());
+        assert!(recognizer("Hierarchy").is_ok());
+        assert!(recognizer("unknown").is_err());
+    }
+}
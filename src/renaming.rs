@@ -0,0 +1,398 @@
+use std::{
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    NaiveDateTime,
+};
+use dashmap::mapref::entry::Entry;
+
+use crate::{conflicts::Claimed, layout::camera_name};
+
+const DEFAULT_DATETIME_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// `{datetime}` or `{datetime:%Y-%m-%d %H.%M.%S}`
+    DateTime(String),
+    /// EXIF `Model`
+    Camera,
+    /// Original name without the extension
+    Name,
+    /// `{seq}` or `{seq:3}` for `001`
+    Seq(usize),
+}
+
+/// Template of new names of files, like `{datetime:%Y%m%d_%H%M%S}_{camera}_{seq}.{ext}`. It
+/// should end with `.{ext}`, the original extension.
+///
+/// Fields of the template:
+/// * `{datetime}`, `{datetime:FORMAT}` - time of the file, the default format is `%Y%m%d_%H%M%S`;
+/// * `{camera}` - EXIF `Model` of the file;
+/// * `{name}` - the original name without the extension;
+/// * `{seq}`, `{seq:WIDTH}` - the least number from 1 which makes the name unique in the folder.
+///
+/// Files of one shot (RAW+JPEG with the same name) get the same name, sidecars are renamed
+/// with their files.
+#[derive(Debug, Clone)]
+pub struct RenameTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+/// A file to rename, it's moved to `folder`.
+#[derive(Debug)]
+pub(crate) struct RenamedFile<'a> {
+    pub path: &'a Path,
+    pub folder: &'a Path,
+    pub sidecars: &'a [PathBuf],
+}
+
+/// New names of the file and its sidecars.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct NewNames {
+    pub file: OsString,
+    pub sidecars: Vec<OsString>,
+}
+
+impl fmt::Display for RenameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl FromStr for RenameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: String| format!("invalid rename template '{}': {}", s, message);
+
+        let stem = s
+            .strip_suffix(".{ext}")
+            .ok_or_else(|| invalid("it should end with .{ext}".to_string()))?;
+
+        let mut parts = Vec::new();
+        let mut rest = stem;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("'{' is not closed".to_string()))?;
+            let field = &rest[start + 1..start + end];
+            let (name, argument) = match field.split_once(':') {
+                Some((name, argument)) => (name, Some(argument)),
+                None => (field, None),
+            };
+            parts.push(match (name, argument) {
+                ("datetime", None) => Part::DateTime(DEFAULT_DATETIME_FORMAT.to_string()),
+                ("datetime", Some(format)) => {
+                    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                        return Err(invalid(format!("invalid format of time '{}'", format)));
+                    }
+                    Part::DateTime(format.to_string())
+                }
+                ("camera", None) => Part::Camera,
+                ("name", None) => Part::Name,
+                ("seq", None) => Part::Seq(1),
+                ("seq", Some(width)) => Part::Seq(
+                    width
+                        .parse()
+                        .map_err(|_| invalid(format!("width of seq should be a number, got '{}'", width)))?,
+                ),
+                ("ext", None) => return Err(invalid("{ext} should be at the end".to_string())),
+                _ => return Err(invalid(format!("unknown field {{{}}}", field))),
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if parts.is_empty() {
+            return Err(invalid("name is empty".to_string()));
+        }
+
+        Ok(RenameTemplate {
+            template: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl RenameTemplate {
+    fn has_seq(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, Part::Seq(_)))
+    }
+
+    // New name without the extension, `name` is the original one of the first file
+    fn stem(&self, datetime: NaiveDateTime, camera: Option<&str>, name: &str, seq: usize) -> String {
+        let stem: String = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::DateTime(format) => datetime.format(format).to_string(),
+                Part::Camera => camera_name(camera),
+                Part::Name => name.to_string(),
+                Part::Seq(width) => format!("{:0width$}", seq, width = width),
+            })
+            .collect();
+        stem.replace(['/', '\\'], "_")
+    }
+
+    /// New names of files of one shot, like RAW+JPEG, and their sidecars. The files get the
+    /// same name with their own extensions, `datetime` and `camera` are of the first file.
+    ///
+    /// With `{seq}` the least number is taken, which makes names of all files and sidecars
+    /// unique: they don't exist and aren't claimed by other files. The names are claimed by
+    /// the files.
+    pub(crate) fn rename(
+        &self,
+        files: &[RenamedFile],
+        datetime: NaiveDateTime,
+        camera: Option<&str>,
+        claimed: &Claimed,
+    ) -> Vec<NewNames> {
+        let name = files
+            .first()
+            .and_then(|file| file.path.file_stem())
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if !self.has_seq() {
+            return new_names(files, &self.stem(datetime, camera, &name, 1));
+        }
+
+        (1..)
+            .map(|seq| new_names(files, &self.stem(datetime, camera, &name, seq)))
+            .find(|names| claim(files, names, claimed))
+            .unwrap() // numbers are endless
+    }
+}
+
+/// `names` of the file with the least numeric suffix which makes them unique in the folder,
+/// like `IMG_0001_1.JPG`. Sidecars get the same suffix. The names are claimed by the file.
+pub(crate) fn with_suffix(file: &RenamedFile, names: &NewNames, claimed: &Claimed) -> NewNames {
+    let name = Path::new(&names.file);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let ext = name.extension().map(|ext| ext.to_string_lossy().to_string());
+
+    (1..)
+        .map(|suffix| {
+            let new_stem = format!("{}_{}", stem, suffix);
+            let new_name = match &ext {
+                Some(ext) => format!("{}.{}", new_stem, ext),
+                None => new_stem.clone(),
+            };
+            let sidecars = names
+                .sidecars
+                .iter()
+                .map(|sidecar| OsString::from(sidecar_name(name, Path::new(sidecar), &new_stem, &new_name)))
+                .collect();
+            vec![NewNames {
+                file: OsString::from(new_name),
+                sidecars,
+            }]
+        })
+        .find(|suffixed| claim(std::slice::from_ref(file), suffixed, claimed))
+        .and_then(|suffixed| suffixed.into_iter().next())
+        .unwrap() // numbers are endless
+}
+
+fn new_names(files: &[RenamedFile], stem: &str) -> Vec<NewNames> {
+    files
+        .iter()
+        .map(|file| {
+            let name = match file.path.extension() {
+                Some(ext) => format!("{}.{}", stem, ext.to_string_lossy()),
+                None => stem.to_string(),
+            };
+            let sidecars = file
+                .sidecars
+                .iter()
+                .map(|sidecar| OsString::from(sidecar_name(file.path, sidecar, stem, &name)))
+                .collect();
+            NewNames {
+                file: OsString::from(name),
+                sidecars,
+            }
+        })
+        .collect()
+}
+
+// `IMG_1.CR2.xmp` is renamed to `<name>.xmp`, `IMG_1.xmp` - to `<stem>.xmp`
+fn sidecar_name(file: &Path, sidecar: &Path, stem: &str, name: &str) -> String {
+    let file_name = file.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    let sidecar_name = sidecar.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let ext = sidecar.extension().unwrap_or_default().to_string_lossy();
+    if sidecar_name.to_lowercase().starts_with(&format!("{}.", file_name)) {
+        format!("{}.{}", name, ext)
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+// Claim all paths or none of them
+fn claim(files: &[RenamedFile], names: &[NewNames], claimed: &Claimed) -> bool {
+    let paths: Vec<(PathBuf, &Path)> = files
+        .iter()
+        .zip(names)
+        .flat_map(|(file, names)| {
+            std::iter::once((&names.file, file.path))
+                .chain(names.sidecars.iter().zip(file.sidecars.iter().map(PathBuf::as_path)))
+                .map(|(name, source)| (file.folder.join(name), source))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let mut taken = Vec::new();
+    for (path, source) in paths {
+        let free = !path.exists()
+            && match claimed.entry(path.clone()) {
+                Entry::Occupied(_) => false,
+                Entry::Vacant(entry) => {
+                    entry.insert(source.to_path_buf());
+                    true
+                }
+            };
+        if !free {
+            for path in taken {
+                claimed.remove(&path);
+            }
+            return false;
+        }
+        taken.push(path);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn datetime() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2022, 10, 2)
+            .unwrap()
+            .and_hms_opt(10, 15, 0)
+            .unwrap()
+    }
+
+    fn names(names: &[NewNames]) -> Vec<String> {
+        names
+            .iter()
+            .flat_map(|names| std::iter::once(&names.file).chain(&names.sidecars))
+            .map(|name| name.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn shot_with_sidecars() {
+        let template: RenameTemplate = "{datetime}_{camera}_{seq}.{ext}".parse().unwrap();
+        let folder = Path::new("test_data/not-exists");
+        let raw_sidecars = [PathBuf::from("IMG_1.xmp")];
+        let jpeg_sidecars = [PathBuf::from("IMG_1.JPG.xmp")];
+        let files = [
+            RenamedFile {
+                path: Path::new("IMG_1.CR2"),
+                folder,
+                sidecars: &raw_sidecars,
+            },
+            RenamedFile {
+                path: Path::new("IMG_1.JPG"),
+                folder,
+                sidecars: &jpeg_sidecars,
+            },
+        ];
+
+        let claimed = Claimed::new();
+        let renamed = template.rename(&files, datetime(), Some("PENTAX K-5"), &claimed);
+        assert_eq!(
+            names(&renamed),
+            vec![
+                "20221002_101500_PENTAX K-5_1.CR2",
+                "20221002_101500_PENTAX K-5_1.xmp",
+                "20221002_101500_PENTAX K-5_1.JPG",
+                "20221002_101500_PENTAX K-5_1.JPG.xmp"
+            ]
+        );
+        assert_eq!(claimed.len(), 4);
+
+        // the next shot of the same second
+        let renamed = template.rename(&files[1..], datetime(), Some("PENTAX K-5"), &claimed);
+        assert_eq!(
+            names(&renamed),
+            vec!["20221002_101500_PENTAX K-5_2.JPG", "20221002_101500_PENTAX K-5_2.JPG.xmp"]
+        );
+    }
+
+    #[test]
+    fn existing_files_are_skipped() {
+        let template: RenameTemplate = "{seq:2}.{ext}".parse().unwrap();
+        let files = [RenamedFile {
+            path: Path::new("IMG_1.jpg"),
+            folder: Path::new("test_data/images"),
+            sidecars: &[],
+        }];
+
+        // test_data/images has 01.jpg
+        let renamed = template.rename(&files, datetime(), None, &Claimed::new());
+        assert_eq!(names(&renamed), vec!["02.jpg"]);
+    }
+
+    #[test]
+    fn without_seq() {
+        let template: RenameTemplate = "{datetime:%Y-%m-%d %H.%M}_{name}.{ext}".parse().unwrap();
+        let files = [RenamedFile {
+            path: Path::new("IMG_1.jpg"),
+            folder: Path::new("test_data/not-exists"),
+            sidecars: &[],
+        }];
+
+        let claimed = Claimed::new();
+        let renamed = template.rename(&files, datetime(), None, &claimed);
+        assert_eq!(names(&renamed), vec!["2022-10-02 10.15_IMG_1.jpg"]);
+        assert!(claimed.is_empty());
+    }
+
+    #[test]
+    fn numeric_suffix() {
+        let sidecars = [PathBuf::from("IMG_1.jpg.xmp")];
+        let file = RenamedFile {
+            path: Path::new("IMG_1.jpg"),
+            folder: Path::new("test_data/images"),
+            sidecars: &sidecars,
+        };
+        // the file is placed as 01.jpg, it exists
+        let new_names = NewNames {
+            file: OsString::from("01.jpg"),
+            sidecars: vec![OsString::from("01.jpg.xmp")],
+        };
+
+        let claimed = Claimed::new();
+        let suffixed = with_suffix(&file, &new_names, &claimed);
+        assert_eq!(names(&[suffixed]), vec!["01_1.jpg", "01_1.jpg.xmp"]);
+        assert_eq!(
+            claimed.get(Path::new("test_data/images/01_1.jpg")).map(|source| source.clone()),
+            Some(PathBuf::from("IMG_1.jpg"))
+        );
+        let suffixed = with_suffix(&file, &new_names, &claimed);
+        assert_eq!(names(&[suffixed]), vec!["01_2.jpg", "01_2.jpg.xmp"]);
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!("{datetime}_{seq}".parse::<RenameTemplate>().is_err());
+        assert!("{ext}_{seq}.{ext}".parse::<RenameTemplate>().is_err());
+        assert!("{datetime:%Q}.{ext}".parse::<RenameTemplate>().is_err());
+        assert!("{seq:x}.{ext}".parse::<RenameTemplate>().is_err());
+        assert!("{unknown}.{ext}".parse::<RenameTemplate>().is_err());
+        assert!(".{ext}".parse::<RenameTemplate>().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{conflicts::Resolution, error::Error, iocommands::TransferMode, plan::Skipped};
+
+/// What is done by [crate::Manager::arrange_files].
+#[derive(Debug, Default)]
+pub struct ArrangeReport {
+    /// Transferred files and sidecars
+    pub transferred: Vec<Transferred>,
+    pub created_folders: Vec<PathBuf>,
+    /// Emptied source folders
+    pub removed_folders: Vec<PathBuf>,
+    /// Files which exist in their folders, or which are placed there by other files of the run
+    pub conflicts: Vec<Conflict>,
+    /// Files which are not images or videos and sidecars without their files
+    pub ignored: Vec<PathBuf>,
+    /// Files and folders which can't be read, like files with broken EXIF
+    pub unreadable: Vec<Failure>,
+    /// Commands which failed, like files which can't be transferred
+    pub failures: Vec<Failure>,
+    /// The run is stopped by [FailurePolicy], commands after the failure aren't executed
+    pub stopped: bool,
+    pub timings: Timings,
+}
+
+/// The file is transferred to `to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transferred {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub mode: TransferMode,
+}
+
+/// The file `path` has the same target as the file `existing`, it's the target itself if it
+/// exists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: PathBuf,
+    pub target: PathBuf,
+    pub existing: PathBuf,
+    pub resolution: Resolution,
+}
+
+/// The file or folder `path` can't be processed.
+#[derive(Debug)]
+pub struct Failure {
+    pub path: PathBuf,
+    pub error: Error,
+}
+
+/// Result of [crate::Manager::undo]. The run is marked as undone only if all its commands are
+/// reverted, so the undo can be repeated after failures.
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    /// Commands of the reverted run, 0 if all runs are undone
+    pub commands: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl UndoReport {
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+}
+
+/// What to do when a command fails or a file can't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// The failure is reported, other commands are executed
+    Continue,
+    /// Commands which are executing are finished, others aren't executed
+    StopAfterCurrent,
+    /// Nothing is executed if a file can't be read or a source of the plan is changed,
+    /// failures while executing stop the run as [FailurePolicy::StopAfterCurrent]
+    AbortBeforeExecute,
+}
+
+impl FailurePolicy {
+    /// Does the failure stop the run.
+    pub(crate) fn stops(&self) -> bool {
+        *self != FailurePolicy::Continue
+    }
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FailurePolicy::Continue => "continue",
+            FailurePolicy::StopAfterCurrent => "stop-after-current",
+            FailurePolicy::AbortBeforeExecute => "abort-before-execute",
+        })
+    }
+}
+
+impl FromStr for FailurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "continue" => Ok(FailurePolicy::Continue),
+            "stop-after-current" | "stop" => Ok(FailurePolicy::StopAfterCurrent),
+            "abort-before-execute" | "abort" => Ok(FailurePolicy::AbortBeforeExecute),
+            _ => Err(format!(
+                "unknown failure policy '{}', expected one of: continue, stop-after-current, abort-before-execute",
+                s
+            )),
+        }
+    }
+}
+
+/// Time of phases of the run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Search of folders of dates and sources
+    pub find_folders: Duration,
+    /// Reading of metadata and making of commands
+    pub read_metadata: Duration,
+    pub create_folders: Duration,
+    pub transfer: Duration,
+    /// Removal of duplicates and emptied folders
+    pub clean_up: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.find_folders + self.read_metadata + self.create_folders + self.transfer + self.clean_up
+    }
+}
+
+impl ArrangeReport {
+    /// Were there files which can't be read or commands which failed.
+    pub fn has_failures(&self) -> bool {
+        !self.unreadable.is_empty() || !self.failures.is_empty()
+    }
+
+    /// Number of conflicts with the resolution.
+    pub fn conflicts_resolved(&self, resolution: Resolution) -> usize {
+        self.conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution == resolution)
+            .count()
+    }
+
+    // Files which aren't arranged with the reasons
+    pub(crate) fn skipped(&self) -> Vec<Skipped> {
+        let ignored = self.ignored.iter().map(|path| Skipped {
+            path: path.clone(),
+            reason: "not an image or video, or a sidecar without its file".to_string(),
+        });
+        let unreadable = self.unreadable.iter().map(|failure| Skipped {
+            path: failure.path.clone(),
+            reason: format!("{:#}", failure.error),
+        });
+        let conflicts = self
+            .conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution == Resolution::Skipped)
+            .map(|conflict| Skipped {
+                path: conflict.path.clone(),
+                reason: match conflict.existing == conflict.target {
+                    true => format!("{} exists", conflict.target.to_string_lossy()),
+                    false => format!(
+                        "{} is placed as {} too",
+                        conflict.existing.to_string_lossy(),
+                        conflict.target.to_string_lossy()
+                    ),
+                },
+            });
+        ignored.chain(unreadable).chain(conflicts).collect()
+    }
+}
@@ -0,0 +1,172 @@
+use std::{collections::HashMap, fmt, path::PathBuf, str::FromStr};
+
+use crate::FileType;
+
+/// Sidecars of Lightroom and darktable (`.xmp`), Apple's edits (`.aae`) and thumbnails of
+/// videos (`.thm`).
+pub(crate) const DEFAULT_SIDECAR_EXTENSIONS: [&str; 3] = ["xmp", "aae", "thm"];
+
+/// How a sidecar's name is made from the name of the file it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SidecarNaming {
+    /// The sidecar's extension replaces the file's one: `IMG_1.xmp` for `IMG_1.CR2`
+    Stem,
+    /// The sidecar's extension is added to the file's name: `IMG_1.CR2.xmp` (darktable)
+    FullName,
+    /// Both conventions are used
+    Both,
+}
+
+impl fmt::Display for SidecarNaming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SidecarNaming::Stem => "stem",
+            SidecarNaming::FullName => "fullname",
+            SidecarNaming::Both => "both",
+        })
+    }
+}
+
+impl FromStr for SidecarNaming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "stem" => Ok(SidecarNaming::Stem),
+            "fullname" | "full" => Ok(SidecarNaming::FullName),
+            "both" => Ok(SidecarNaming::Both),
+            _ => Err(format!(
+                "unknown sidecar naming '{}', expected one of: stem, fullname, both",
+                s
+            )),
+        }
+    }
+}
+
+/// Find the file each sidecar belongs to. Names are compared ignoring case, because cameras
+/// write `MVI_0001.MOV` and `MVI_0001.THM`, but editors may write `mvi_0001.mov.xmp`.
+///
+/// If several files have the same stem (RAW+JPEG), a sidecar by [SidecarNaming::Stem] belongs to
+/// the RAW, because editors write sidecars for RAWs.
+///
+/// Return sidecars of each file and sidecars without a file.
+pub(crate) fn group_sidecars(
+    files: &[(PathBuf, FileType)],
+    sidecars: Vec<PathBuf>,
+    naming: SidecarNaming,
+) -> (HashMap<PathBuf, Vec<PathBuf>>, Vec<PathBuf>) {
+    let lowercase = |name: &std::ffi::OsStr| name.to_string_lossy().to_lowercase();
+
+    let mut by_name = HashMap::new();
+    let mut by_stem = HashMap::<String, &(PathBuf, FileType)>::new();
+    for file in files {
+        let (path, file_type) = file;
+        if let Some(name) = path.file_name() {
+            by_name.insert(lowercase(name), path);
+        }
+        if let Some(stem) = path.file_stem() {
+            let stem = lowercase(stem);
+            let is_raw = *file_type == FileType::RAW;
+            match by_stem.get(&stem) {
+                Some((_, FileType::RAW)) => {}
+                Some(_) if !is_raw => {}
+                _ => {
+                    by_stem.insert(stem, file);
+                }
+            }
+        }
+    }
+
+    let mut grouped = HashMap::<PathBuf, Vec<PathBuf>>::new();
+    let mut orphans = Vec::new();
+    for sidecar in sidecars {
+        // IMG_1.CR2.xmp => IMG_1.CR2 as the full name of the file and IMG_1 as its stem
+        let name = sidecar.file_stem().map(lowercase);
+        let full_name = name.as_ref().filter(|_| naming != SidecarNaming::Stem).and_then(|name| by_name.get(name));
+        let stem = name
+            .as_ref()
+            .filter(|_| naming != SidecarNaming::FullName)
+            .and_then(|name| by_stem.get(name))
+            .map(|(path, _)| path);
+
+        match full_name.copied().or(stem) {
+            Some(file) => grouped.entry(file.clone()).or_default().push(sidecar),
+            None => orphans.push(sidecar),
+        }
+    }
+
+    (grouped, orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<(PathBuf, FileType)> {
+        vec![
+            (PathBuf::from("IMG_1.JPG"), FileType::IMAGE),
+            (PathBuf::from("IMG_1.CR2"), FileType::RAW),
+            (PathBuf::from("IMG_2.JPG"), FileType::IMAGE),
+            (PathBuf::from("MVI_3.MOV"), FileType::MOVIE),
+        ]
+    }
+
+    fn sidecars(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn by_stem() {
+        let (grouped, orphans) = group_sidecars(
+            &files(),
+            sidecars(&["IMG_2.AAE", "mvi_3.thm"]),
+            SidecarNaming::Both,
+        );
+        assert_eq!(grouped[&PathBuf::from("IMG_2.JPG")], sidecars(&["IMG_2.AAE"]));
+        assert_eq!(grouped[&PathBuf::from("MVI_3.MOV")], sidecars(&["mvi_3.thm"]));
+        assert!(orphans.is_empty());
+    }
+
+    #[test]
+    fn raw_wins_by_stem() {
+        let (grouped, _) = group_sidecars(&files(), sidecars(&["IMG_1.xmp"]), SidecarNaming::Both);
+        assert_eq!(grouped[&PathBuf::from("IMG_1.CR2")], sidecars(&["IMG_1.xmp"]));
+    }
+
+    #[test]
+    fn by_full_name() {
+        let (grouped, _) = group_sidecars(
+            &files(),
+            sidecars(&["IMG_1.JPG.xmp", "IMG_1.CR2.xmp"]),
+            SidecarNaming::Both,
+        );
+        assert_eq!(grouped[&PathBuf::from("IMG_1.JPG")], sidecars(&["IMG_1.JPG.xmp"]));
+        assert_eq!(grouped[&PathBuf::from("IMG_1.CR2")], sidecars(&["IMG_1.CR2.xmp"]));
+    }
+
+    #[test]
+    fn only_configured_naming() {
+        let (grouped, orphans) = group_sidecars(
+            &files(),
+            sidecars(&["IMG_1.CR2.xmp", "IMG_2.xmp"]),
+            SidecarNaming::FullName,
+        );
+        assert_eq!(grouped.len(), 1);
+        assert_eq!(orphans, sidecars(&["IMG_2.xmp"]));
+
+        let (grouped, orphans) = group_sidecars(
+            &files(),
+            sidecars(&["IMG_1.CR2.xmp", "IMG_2.xmp"]),
+            SidecarNaming::Stem,
+        );
+        assert_eq!(grouped[&PathBuf::from("IMG_2.JPG")], sidecars(&["IMG_2.xmp"]));
+        assert_eq!(orphans, sidecars(&["IMG_1.CR2.xmp"]));
+    }
+
+    #[test]
+    fn without_file() {
+        let (grouped, orphans) = group_sidecars(&files(), sidecars(&["IMG_9.xmp"]), SidecarNaming::Both);
+        assert!(grouped.is_empty());
+        assert_eq!(orphans, sidecars(&["IMG_9.xmp"]));
+    }
+}
@@ -0,0 +1,96 @@
+use std::{fs::File, io::Read, path::Path};
+
+use crate::exifreader::{is_heif, VideoContainer, HEADER_SIZE};
+
+/// Kind of the file by its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Magic {
+    /// JPEG, PNG, WebP, HEIF, AVIF
+    Image,
+    /// TIFF container, it's a TIFF image or a RAW of most cameras (NEF, ARW, DNG, PEF, CR2, ...)
+    Tiff,
+    /// Formats used for RAWs only: CR3, RAF, ORF, RW2, CRW, MRW, X3F
+    Raw,
+    /// MP4, MOV, AVI, MTS
+    Movie,
+}
+
+// Starts of RAW formats which are not TIFF
+const RAW_MAGICS: [&[u8]; 8] = [
+    b"FUJIFILMCCD-RAW", // RAF
+    b"IIRO",            // ORF
+    b"IIRS",            // ORF
+    b"MMOR",            // ORF
+    b"IIU\0",           // RW2
+    b"II\x1a\0\0\0HEAPCCDR", // CRW
+    b"\0MRM",           // MRW
+    b"FOVb",            // X3F
+];
+
+/// Read the first bytes of the file and detect its kind. Return None for unknown formats and
+/// empty or unreadable files.
+pub(crate) fn sniff_file<P: AsRef<Path>>(path: P) -> Option<Magic> {
+    let mut header = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(HEADER_SIZE).read_to_end(&mut header))
+        .ok()?;
+    sniff(&header)
+}
+
+pub(crate) fn sniff(header: &[u8]) -> Option<Magic> {
+    let starts = |magic: &[u8]| header.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    if starts(b"\xff\xd8\xff") || starts(b"\x89PNG\r\n\x1a\n") || starts(b"RIFF") && at(8, b"WEBP") {
+        Some(Magic::Image)
+    } else if at(4, b"ftyp") && at(8, b"crx ") {
+        Some(Magic::Raw) // CR3
+    } else if is_heif(header) {
+        Some(Magic::Image)
+    } else if VideoContainer::detect(header).is_some() {
+        Some(Magic::Movie)
+    } else if RAW_MAGICS.iter().any(|magic| starts(magic)) {
+        Some(Magic::Raw)
+    } else if starts(b"II*\0") || starts(b"MM\0*") {
+        Some(Magic::Tiff)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images() {
+        assert_eq!(sniff_file("test_data/images/01.jpg"), Some(Magic::Image));
+        assert_eq!(sniff_file("test_data/images/08.heic"), Some(Magic::Image));
+        assert_eq!(sniff_file("test_data/images/09.avif"), Some(Magic::Image));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(Magic::Image));
+    }
+
+    #[test]
+    fn raws() {
+        assert_eq!(sniff(b"II*\0\x08\0\0\0\0\0\0\0\0\0\0\0"), Some(Magic::Tiff));
+        assert_eq!(sniff(b"\0\0\0\x18ftypcrx \0\0\0\x01crx isom"), Some(Magic::Raw));
+        assert_eq!(sniff(b"FUJIFILMCCD-RAW 0201FF383501"), Some(Magic::Raw));
+        assert_eq!(sniff(b"IIU\0\x18\0\0\0\x88\xe7\x74\xd8"), Some(Magic::Raw));
+    }
+
+    #[test]
+    fn movies() {
+        assert_eq!(sniff_file("test_data/images/04.mp4"), Some(Magic::Movie));
+        assert_eq!(sniff_file("test_data/images/05.mov"), Some(Magic::Movie));
+        assert_eq!(sniff_file("test_data/images/06.avi"), Some(Magic::Movie));
+        assert_eq!(sniff_file("test_data/images/07.mts"), Some(Magic::Movie));
+    }
+
+    #[test]
+    fn others() {
+        assert_eq!(sniff(b"some notes about the trip"), None);
+        assert_eq!(sniff(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1\0\0\0\0\0\0\0\0"), None); // Thumbs.db
+        assert_eq!(sniff_file("test_data/suite/somefile"), None);
+        assert_eq!(sniff_file("test_data/not-exists"), None);
+    }
+}
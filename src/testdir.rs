@@ -0,0 +1,11 @@
+use std::{fs, path::PathBuf};
+
+/// Empty directory for the test `test_name` of the module, files of the previous run are removed.
+pub(crate) fn test_dir(module: &str, test_name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("photoman_{}_{}", module, std::process::id()))
+        .join(test_name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
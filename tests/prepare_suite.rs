@@ -1,5 +1,4 @@
 use lazy_static::lazy_static;
-use tracing::Dispatch;
 use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 
 use std::{
@@ -16,7 +15,14 @@ lazy_static! {
 /// Prepare suite for testing.
 ///
 /// Return test's working folder. Test can free modify content of this folder.
+#[allow(dead_code)]
 pub fn prepare_suite(test_name: &str) -> Result<PathBuf, io::Error> {
+    prepare_suite_from("test_data/suite", test_name)
+}
+
+/// Prepare suite for testing from other files than `test_data/suite`.
+#[allow(dead_code)]
+pub fn prepare_suite_from<P: AsRef<Path>>(suite: P, test_name: &str) -> Result<PathBuf, io::Error> {
    let subscriber =  tracing_subscriber::FmtSubscriber::builder()
         .log_internal_errors(true)
         .compact()
@@ -26,13 +32,14 @@ pub fn prepare_suite(test_name: &str) -> Result<PathBuf, io::Error> {
         .with_ansi(true)
         .finish();
 
-    tracing::subscriber::set_global_default(subscriber);
+    // several tests share one process, only the first one installs the subscriber
+    let _ = tracing::subscriber::set_global_default(subscriber);
 
     let test_dir = std::env::temp_dir()
         .join(EXECUTION_TIMESTAMP.as_str())
         .join(test_name);
 
-    copy_recursively("test_data/suite", &test_dir).map(|_| test_dir.to_path_buf())
+    copy_recursively(suite, &test_dir).map(|_| test_dir.to_path_buf())
 }
 
 /// Copy files from source to destination recursively.
@@ -2,7 +2,8 @@
 extern crate chrono;
 use std::{fs};
 
-use photoman::Manager;
+use chrono::NaiveTime;
+use photoman::{ClockCorrections, Manager};
 use spectral::assert_that;
 use spectral::prelude::PathAssertions;
 
@@ -16,7 +17,7 @@ pub fn standard_execution() {
 
     if let Ok(test_dir) = r {
         let mut manager = Manager::new().work_dir(&test_dir);
-        manager.arrange_files();
+        manager.arrange_files().unwrap();
 
         let dir_2020_06_21 = test_dir.join("2020-06-21");
         let dir_2020_06_21_raw = dir_2020_06_21.join("raw");
@@ -39,6 +40,51 @@ pub fn standard_execution() {
 
 }
 
+#[test]
+pub fn day_starts_at() {
+    let r = prepare_suite("day_starts_at");
+
+    if let Ok(test_dir) = r {
+        // 01.jpg is taken at 22:20, 02.JPG and 03.JPG at 17:39 and 17:41
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .day_starts_at(NaiveTime::from_hms_opt(23, 0, 0).unwrap());
+        manager.arrange_files().unwrap();
+
+        let dir_2020_06_20 = test_dir.join("2020-06-20");
+        let dir_2022_10_01 = test_dir.join("2022-10-01");
+
+        assert_that(&dir_2020_06_20.join("01.jpg")).exists();
+        assert_that(&dir_2020_06_20.join("raw").join("01.raw")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+
+        assert_that(&dir_2022_10_01.join("02.JPG")).exists();
+        assert_that(&dir_2022_10_01.join("03.JPG")).exists();
+    }
+}
+
+#[test]
+pub fn clock_corrections() {
+    let r = prepare_suite("clock_corrections");
+
+    if let Ok(test_dir) = r {
+        // 01.jpg is taken at 22:20, the correction moves it to the next day
+        let corrections = ClockCorrections::from_file("test_data/clock-corrections.conf").unwrap();
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .clock_corrections(corrections);
+        manager.arrange_files().unwrap();
+
+        let dir_2020_06_22 = test_dir.join("2020-06-22");
+
+        assert_that(&dir_2020_06_22.join("01.jpg")).exists();
+        assert_that(&dir_2020_06_22.join("raw").join("01.raw")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("03.JPG")).exists();
+    }
+}
+
 #[allow(non_snake_case)]
 #[test]
 pub fn dry_run() {
@@ -49,7 +95,7 @@ pub fn dry_run() {
             .work_dir(&test_dir)
             .dry_run();
 
-        manager.arrange_files();
+        manager.arrange_files().unwrap();
 
         let dir_2020_06_21 = test_dir.join("2020-06-21");
         let dir_2022_10_02 = test_dir.join("2022-10-02 (Pushkin)");        
@@ -0,0 +1,67 @@
+use std::fs;
+
+use photoman::Manager;
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite;
+
+const CACHE_NAME: &str = ".photoman-cache.jsonl";
+
+#[test]
+pub fn cache_follows_files() {
+    let r = prepare_suite("cache_follows_files");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        // tags are kept for new paths, moved sources are dropped
+        let cache = fs::read_to_string(test_dir.join(CACHE_NAME)).unwrap();
+        assert!(cache.contains("2020-06-21/01.jpg"));
+        assert!(cache.contains("2020-06-21/raw/01.raw"));
+        assert!(cache.contains("IMGP2011/02.JPG"));
+        assert!(!cache.contains("IMGP1011"));
+
+        // the cache isn't arranged as a file of the work dir
+        let report = manager.arrange_files().unwrap();
+        assert_eq!(report.ignored, vec![test_dir.join("somefile")]);
+        assert_that(&test_dir.join(CACHE_NAME)).exists();
+    }
+}
+
+#[test]
+pub fn cache_is_not_written() {
+    let r = prepare_suite("cache_is_not_written");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir).dry_run();
+        manager.arrange_files().unwrap();
+        assert_that(&test_dir.join(CACHE_NAME)).does_not_exist();
+
+        let mut manager = Manager::new().work_dir(&test_dir).no_cache();
+        manager.arrange_files().unwrap();
+        assert_that(&test_dir.join(CACHE_NAME)).does_not_exist();
+    }
+}
+
+#[cfg(unix)]
+#[test]
+pub fn cache_of_other_reader() {
+    let r = prepare_suite("cache_of_other_reader");
+
+    if let Ok(test_dir) = r {
+        // the plan caches tags of the built-in reader without changes of files
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.plan(test_dir.with_extension("plan.json")).unwrap();
+        assert_that(&test_dir.join(CACHE_NAME)).exists();
+
+        // exiftool reads files again, the stub dates all of them 2019-05-04
+        let mut manager = Manager::new().work_dir(&test_dir).use_exiftool("test_data/exiftool-stub");
+        manager.arrange_files().unwrap();
+        assert_that(&test_dir.join("2019-05-04").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use photoman::{ConflictPolicy, Manager, Resolution, TransferMode};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite;
+
+#[test]
+pub fn conflict_skip_identical() {
+    let r = prepare_suite("conflict_skip_identical");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(ConflictPolicy::SkipIdentical);
+        manager.arrange_files().unwrap();
+
+        // IMGP2011/02.JPG is a copy of the existing file, it's deleted
+        let dir_2022_10_02 = test_dir.join("2022-10-02 (Pushkin)");
+        assert_that(&dir_2022_10_02.join("02.JPG")).exists();
+        assert_that(&dir_2022_10_02.join("03.JPG")).exists();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn conflict_skip_different() {
+    let r = prepare_suite("conflict_skip_different");
+
+    if let Ok(test_dir) = r {
+        let existing = test_dir.join("2022-10-02 (Pushkin)").join("02.JPG");
+        fs::write(&existing, "edited image").unwrap();
+
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(ConflictPolicy::SkipIdentical);
+        manager.arrange_files().unwrap();
+
+        // files differ, both are kept
+        assert_that(&test_dir.join("IMGP2011").join("02.JPG")).exists();
+        assert_eq!(fs::read(&existing).unwrap(), b"edited image");
+    }
+}
+
+#[test]
+pub fn conflict_identical_copy() {
+    let r = prepare_suite("conflict_identical_copy");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .transfer_mode(TransferMode::Copy)
+            .conflict_policy(ConflictPolicy::SkipIdentical);
+        manager.arrange_files().unwrap();
+
+        // sources are kept by copying
+        assert_that(&test_dir.join("IMGP2011").join("02.JPG")).exists();
+    }
+}
+
+#[test]
+pub fn conflict_rename() {
+    let r = prepare_suite("conflict_rename");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir).conflict_policy(ConflictPolicy::Rename);
+        manager.arrange_files().unwrap();
+
+        let dir_2022_10_02 = test_dir.join("2022-10-02 (Pushkin)");
+        assert_that(&dir_2022_10_02.join("02.JPG")).exists();
+        assert_that(&dir_2022_10_02.join("02_1.JPG")).exists();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn conflict_keep_newer() {
+    let r = prepare_suite("conflict_keep_newer");
+
+    if let Ok(test_dir) = r {
+        let existing = test_dir.join("2022-10-02 (Pushkin)").join("02.JPG");
+        fs::write(&existing, "old image").unwrap();
+        let file = fs::File::options().write(true).open(&existing).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(ConflictPolicy::KeepNewer);
+        manager.arrange_files().unwrap();
+
+        // the old file is replaced
+        assert_ne!(fs::read(&existing).unwrap(), b"old image");
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn conflict_overwrite() {
+    let r = prepare_suite("conflict_overwrite");
+
+    if let Ok(test_dir) = r {
+        let existing = test_dir.join("2022-10-02 (Pushkin)").join("02.JPG");
+        fs::write(&existing, "edited image").unwrap();
+
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(ConflictPolicy::Overwrite);
+        manager.arrange_files().unwrap();
+
+        assert_ne!(fs::read(&existing).unwrap(), b"edited image");
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+    }
+}
+
+// IMGP3011/02.JPG is 03.JPG, it's placed in the same folder as IMGP2011/02.JPG
+fn prepare_same_names(test_name: &str) -> Option<std::path::PathBuf> {
+    let test_dir = prepare_suite(test_name).ok()?;
+    fs::remove_file(test_dir.join("2022-10-02 (Pushkin)").join("02.JPG")).unwrap();
+    fs::create_dir(test_dir.join("IMGP3011")).unwrap();
+    fs::copy(test_dir.join("IMGP2011").join("03.JPG"), test_dir.join("IMGP3011").join("02.JPG")).unwrap();
+    Some(test_dir)
+}
+
+#[test]
+pub fn same_names_in_run_skip() {
+    if let Some(test_dir) = prepare_same_names("same_names_in_run_skip") {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        // one file is placed, another one is left in its folder
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("02.JPG")).exists();
+        let left = [test_dir.join("IMGP2011"), test_dir.join("IMGP3011")]
+            .iter()
+            .filter(|dir| dir.join("02.JPG").exists())
+            .count();
+        assert_eq!(left, 1);
+    }
+}
+
+#[test]
+pub fn same_names_in_run_overwrite() {
+    if let Some(test_dir) = prepare_same_names("same_names_in_run_overwrite") {
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(ConflictPolicy::Overwrite);
+        manager.arrange_files().unwrap();
+
+        // files of the run don't replace each other
+        let dir_2022_10_02 = test_dir.join("2022-10-02 (Pushkin)");
+        assert_that(&dir_2022_10_02.join("02.JPG")).exists();
+        assert_that(&dir_2022_10_02.join("02_1.JPG")).exists();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+        assert_that(&test_dir.join("IMGP3011")).does_not_exist();
+    }
+}
+
+// Both files of the run and the existing file are kept: the first file is compared with the
+// existing one, the second one is compared with the first one and can't replace it
+fn two_sources_and_existing(test_name: &str, policy: ConflictPolicy) {
+    if let Some(test_dir) = prepare_same_names(test_name) {
+        let dir_2022_10_02 = test_dir.join("2022-10-02 (Pushkin)");
+        let existing = dir_2022_10_02.join("02.JPG");
+        fs::write(&existing, "old image").unwrap();
+        let file = fs::File::options().write(true).open(&existing).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+        let sources = [test_dir.join("IMGP2011").join("02.JPG"), test_dir.join("IMGP3011").join("02.JPG")];
+        let contents: Vec<Vec<u8>> = sources.iter().map(|source| fs::read(source).unwrap()).collect();
+
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(policy);
+        let report = manager.arrange_files().unwrap();
+
+        assert_eq!(report.conflicts.len(), 2);
+        assert_eq!(report.conflicts_resolved(Resolution::Overwritten), 1);
+
+        // the existing file is replaced by one of files, another one isn't lost
+        let placed = fs::read(&existing).unwrap();
+        assert!(contents.contains(&placed));
+        let (other, other_content) = sources
+            .iter()
+            .zip(&contents)
+            .find(|(_, content)| **content != placed)
+            .unwrap();
+        let renamed = dir_2022_10_02.join("02_1.JPG");
+        if policy == ConflictPolicy::Overwrite || renamed.exists() {
+            assert_eq!(report.conflicts_resolved(Resolution::Renamed), 1);
+            assert_eq!(&fs::read(&renamed).unwrap(), other_content);
+        } else {
+            assert_eq!(report.conflicts_resolved(Resolution::Skipped), 1);
+            assert_eq!(&fs::read(other).unwrap(), other_content);
+        }
+    }
+}
+
+#[test]
+pub fn same_names_and_existing_overwrite() {
+    two_sources_and_existing("same_names_and_existing_overwrite", ConflictPolicy::Overwrite);
+}
+
+#[test]
+pub fn same_names_and_existing_keep_newer() {
+    two_sources_and_existing("same_names_and_existing_keep_newer", ConflictPolicy::KeepNewer);
+}
+
+// IMGP3011/02.JPG is the copy of IMGP2011/02.JPG, the target of both is free
+fn prepare_identical_in_run(test_name: &str) -> Option<std::path::PathBuf> {
+    let test_dir = prepare_suite(test_name).ok()?;
+    fs::remove_file(test_dir.join("2022-10-02 (Pushkin)").join("02.JPG")).unwrap();
+    fs::create_dir(test_dir.join("IMGP3011")).unwrap();
+    fs::copy(test_dir.join("IMGP2011").join("02.JPG"), test_dir.join("IMGP3011").join("02.JPG")).unwrap();
+    Some(test_dir)
+}
+
+#[test]
+pub fn identical_in_run_undo() {
+    if let Some(test_dir) = prepare_identical_in_run("identical_in_run_undo") {
+        let content = fs::read(test_dir.join("IMGP2011").join("02.JPG")).unwrap();
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(ConflictPolicy::SkipIdentical);
+        let report = manager.arrange_files().unwrap();
+
+        // one file is placed, its copy is removed
+        assert!(!report.has_failures());
+        assert_eq!(report.conflicts_resolved(Resolution::Identical), 1);
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("02.JPG")).exists();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+        assert_that(&test_dir.join("IMGP3011")).does_not_exist();
+
+        // the removed copy is restored from the placed file
+        let report = Manager::new().work_dir(&test_dir).undo().unwrap();
+        assert!(!report.has_failures());
+        assert_eq!(fs::read(test_dir.join("IMGP2011").join("02.JPG")).unwrap(), content);
+        assert_eq!(fs::read(test_dir.join("IMGP3011").join("02.JPG")).unwrap(), content);
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("02.JPG")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn identical_in_run_apply() {
+    if let Some(test_dir) = prepare_identical_in_run("identical_in_run_apply") {
+        let plan_file = test_dir.with_extension("plan.json");
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .conflict_policy(ConflictPolicy::SkipIdentical);
+        manager.plan(&plan_file).unwrap();
+
+        let report = Manager::new().dry_run().apply(&plan_file).unwrap();
+        assert!(!report.has_failures());
+
+        let report = Manager::new().apply(&plan_file).unwrap();
+        assert!(!report.has_failures());
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("02.JPG")).exists();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+        assert_that(&test_dir.join("IMGP3011")).does_not_exist();
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+use photoman::{DateSource, Error, Manager};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::{prepare_suite, prepare_suite_from};
+
+#[cfg(unix)]
+#[test]
+pub fn arrange_with_exiftool() {
+    let r = prepare_suite("arrange_with_exiftool");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .use_exiftool("test_data/exiftool-stub");
+        manager.arrange_files().unwrap();
+
+        // stub reports the same date for every file
+        let dir_2019_05_04 = test_dir.join("2019-05-04");
+
+        assert_that(&dir_2019_05_04.join("01.jpg")).exists();
+        assert_that(&dir_2019_05_04.join("raw").join("01.raw")).exists();
+        assert_that(&dir_2019_05_04.join("02.JPG")).exists();
+        assert_that(&dir_2019_05_04.join("03.JPG")).exists();
+
+        assert_that(&test_dir.join("IMGP1011")).does_not_exist();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("02.JPG")).exists();
+    }
+}
+
+// mvhd dates are UTC, both readers place the video by the local date
+#[cfg(unix)]
+#[test]
+pub fn same_video_folder_with_exiftool() {
+    // 2020-06-21 19:00 UTC is 2020-06-22 in +05:00
+    std::env::set_var("TZ", "UTC-5");
+    for (name, exiftool) in [
+        ("video_folder_built_in", None),
+        ("video_folder_exiftool", Some("test_data/exiftool-quicktime-stub")),
+    ] {
+        if let Ok(test_dir) = prepare_suite_from("test_data/suite-video", name) {
+            fs::copy("test_data/images/04.mp4", test_dir.join("DCIM").join("04.mp4")).unwrap();
+            let mut manager = Manager::new().work_dir(&test_dir);
+            if let Some(exiftool) = exiftool {
+                manager = manager.use_exiftool(exiftool);
+            }
+            manager.arrange_files().unwrap();
+
+            assert_that(&test_dir.join("2020-06-22").join("04.mp4")).exists();
+        }
+    }
+}
+
+// exiftool can't be asked about a name which isn't UTF-8, the file is reported
+#[cfg(unix)]
+#[test]
+pub fn non_utf8_name_with_exiftool() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let r = prepare_suite("non_utf8_name_with_exiftool");
+
+    if let Ok(test_dir) = r {
+        let odd = test_dir.join("IMGP2011").join(OsStr::from_bytes(b"05\xff.jpg"));
+        fs::copy(test_dir.join("IMGP2011").join("03.JPG"), &odd).unwrap();
+
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .use_exiftool("test_data/exiftool-stub")
+            .date_sources(vec![DateSource::DateTimeOriginal, DateSource::FileName]);
+        let report = manager.arrange_files().unwrap();
+
+        assert_eq!(report.unreadable.len(), 1);
+        assert_eq!(report.unreadable[0].path, odd);
+        let cause = report.unreadable[0].error.cause();
+        assert!(matches!(cause, Error::Io(e) if e.to_string().contains("isn't valid UTF-8")));
+        assert_that(&odd).exists();
+        assert_that(&test_dir.join("2019-05-04").join("03.JPG")).exists();
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+
+use photoman::{DateSource, Error, FailurePolicy, Manager};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite;
+
+// the file 2020-06-21 is in place of the folder for IMGP1011
+fn prepare_blocked_folder(test_name: &str) -> Option<std::path::PathBuf> {
+    let test_dir = prepare_suite(test_name).ok()?;
+    fs::write(test_dir.join("2020-06-21"), "not a folder").unwrap();
+    Some(test_dir)
+}
+
+#[test]
+pub fn failure_continue() {
+    if let Some(test_dir) = prepare_blocked_folder("failure_continue") {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        let report = manager.arrange_files().unwrap();
+
+        // files of the folder are skipped, others are transferred
+        assert!(report.has_failures());
+        assert!(!report.stopped);
+        assert!(report
+            .failures
+            .iter()
+            .any(|failure| matches!(failure.error, Error::FolderNotCreated(_))));
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+        assert_that(&test_dir.join("IMGP1011").join("01.raw")).exists();
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("03.JPG")).exists();
+    }
+}
+
+#[test]
+pub fn failure_stop_after_current() {
+    if let Some(test_dir) = prepare_blocked_folder("failure_stop_after_current") {
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .failure_policy(FailurePolicy::StopAfterCurrent);
+        let report = manager.arrange_files().unwrap();
+
+        // folders are created before transfers, nothing is transferred
+        assert!(report.stopped);
+        assert!(report.transferred.is_empty());
+        assert_that(&test_dir.join("IMGP2011").join("03.JPG")).exists();
+        assert_that(&test_dir.join("IMGP1011")).exists();
+    }
+}
+
+#[test]
+pub fn failure_abort_before_execute() {
+    let r = prepare_suite("failure_abort_before_execute");
+
+    if let Ok(test_dir) = r {
+        // JPEG header without EXIF
+        let broken = test_dir.join("IMGP2011").join("04.JPG");
+        fs::write(&broken, [0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F', b'I', b'F', 0]).unwrap();
+
+        // without the modification time the file has no date
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .date_sources(vec![DateSource::DateTimeOriginal, DateSource::FileName])
+            .failure_policy(FailurePolicy::AbortBeforeExecute);
+        let report = manager.arrange_files().unwrap();
+
+        assert!(report.stopped);
+        assert_eq!(report.unreadable.len(), 1);
+        assert!(report.created_folders.is_empty());
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+        assert_that(&test_dir.join("IMGP2011").join("03.JPG")).exists();
+    }
+}
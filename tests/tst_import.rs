@@ -0,0 +1,65 @@
+use photoman::{Manager, TransferMode};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::{prepare_suite, prepare_suite_from};
+
+#[test]
+pub fn import_into_library() {
+    let library = prepare_suite("import_library");
+    let card = prepare_suite_from("test_data/suite-nested", "import_card");
+
+    if let (Ok(library), Ok(card)) = (library, card) {
+        let mut manager = Manager::new().work_dir(&library).import_from(vec![card.clone()]);
+        manager.arrange_files().unwrap();
+
+        let dir_2022_10_02 = library.join("2022-10-02 (Pushkin)");
+
+        assert_that(&library.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&dir_2022_10_02.join("03.JPG")).exists();
+        assert_that(&library.join("2022-10-02")).does_not_exist();
+
+        // the library already has 02.JPG
+        assert_that(&card.join("DCIM").join("100CANON").join("02.JPG")).exists();
+        assert_that(&card.join("DCIM").join("101CANON")).does_not_exist();
+        assert_that(&card).exists();
+
+        // folders of the library are not arranged
+        assert_that(&library.join("IMGP1011").join("01.jpg")).exists();
+    }
+}
+
+#[test]
+pub fn import_copies() {
+    let library = prepare_suite("import_copies_library");
+    let other_library = prepare_suite("import_copies_other_library");
+    let card = prepare_suite_from("test_data/suite-nested", "import_copies_card");
+
+    if let (Ok(library), Ok(other_library), Ok(card)) = (library, other_library, card) {
+        let deeper = card.join("DCIM").join("101CANON").join("deeper");
+
+        let mut manager = Manager::new()
+            .work_dir(&library)
+            .import_from(vec![card.clone()])
+            .transfer_mode(TransferMode::Copy);
+        manager.arrange_files().unwrap();
+
+        // the card is kept as is
+        assert_that(&library.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&library.join("2022-10-02 (Pushkin)").join("03.JPG")).exists();
+        assert_that(&card.join("01.jpg")).exists();
+        assert_that(&deeper.join("03.JPG")).exists();
+
+        let mut manager = Manager::new()
+            .work_dir(&other_library)
+            .import_from(vec![card.clone()])
+            .transfer_mode(TransferMode::CopyVerifyDelete);
+        manager.arrange_files().unwrap();
+
+        assert_that(&other_library.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&card.join("01.jpg")).does_not_exist();
+        assert_that(&deeper.join("03.JPG")).does_not_exist();
+    }
+}
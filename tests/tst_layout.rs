@@ -0,0 +1,47 @@
+use photoman::{Layout, Manager};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite_from;
+
+#[test]
+pub fn year_month_layout() {
+    let r = prepare_suite_from("test_data/suite-layout", "year_month_layout");
+
+    if let Ok(test_dir) = r {
+        let layout: Layout = "{year}/{year}-{month}/{date} {event}/{raw?}".parse().unwrap();
+        let mut manager = Manager::new().work_dir(&test_dir).layout(layout);
+        manager.arrange_files().unwrap();
+
+        let dir_2020_06_21 = test_dir.join("2020").join("2020-06").join("2020-06-21");
+        assert_that(&dir_2020_06_21.join("01.jpg")).exists();
+        assert_that(&dir_2020_06_21.join("raw").join("01.raw")).exists();
+
+        // the existing folder of the date is reused
+        let dir_2022_10_02 = test_dir.join("2022").join("2022-10").join("2022-10-02 (Pushkin)");
+        assert_that(&dir_2022_10_02.join("03.JPG")).exists();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn camera_layout() {
+    let r = prepare_suite_from("test_data/suite-layout", "camera_layout");
+
+    if let Ok(test_dir) = r {
+        let layout: Layout = "{camera}/{type}/{date}".parse().unwrap();
+        let mut manager = Manager::new().work_dir(&test_dir).layout(layout);
+        manager.arrange_files().unwrap();
+
+        let camera_dir = test_dir.join("DSC-RX100M7");
+        assert_that(&camera_dir.join("image").join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&camera_dir.join("raw").join("2020-06-21").join("01.raw")).exists();
+        assert_that(&camera_dir.join("image").join("2022-10-02").join("03.JPG")).exists();
+
+        // folders of another layout are arranged
+        assert_that(&camera_dir.join("image").join("2022-10-02").join("02.JPG")).exists();
+        assert_that(&test_dir.join("2022")).does_not_exist();
+    }
+}
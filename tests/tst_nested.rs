@@ -0,0 +1,73 @@
+use std::fs;
+
+use photoman::{Manager, SymlinkPolicy};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite_from;
+
+#[test]
+pub fn nested_folders() {
+    let r = prepare_suite_from("test_data/suite-nested", "nested_folders");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2022-10-02").join("02.JPG")).exists();
+        assert_that(&test_dir.join("2022-10-02").join("03.JPG")).exists();
+
+        // the whole emptied tree is removed
+        assert_that(&test_dir.join("DCIM")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn max_depth() {
+    let r = prepare_suite_from("test_data/suite-nested", "max_depth");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir).max_depth(2);
+        manager.arrange_files().unwrap();
+
+        let dcim = test_dir.join("DCIM");
+
+        assert_that(&test_dir.join("2022-10-02").join("02.JPG")).exists();
+        assert_that(&dcim.join("100CANON")).does_not_exist();
+
+        // DCIM/101CANON/deeper is the 3rd level
+        assert_that(&dcim.join("101CANON").join("deeper").join("03.JPG")).exists();
+    }
+}
+
+#[cfg(unix)]
+#[test]
+pub fn symlinks() {
+    for policy in [SymlinkPolicy::Skip, SymlinkPolicy::Follow] {
+        let r = prepare_suite_from("test_data/suite-nested", &format!("symlinks_{}", policy));
+
+        if let Ok(test_dir) = r {
+            let outside = test_dir.with_file_name(format!("symlinks_{}_outside", policy));
+            fs::create_dir_all(&outside).unwrap();
+            fs::copy("test_data/images/01.jpg", outside.join("04.jpg")).unwrap();
+            std::os::unix::fs::symlink(&outside, test_dir.join("linked")).unwrap();
+
+            let mut manager = Manager::new().work_dir(&test_dir).symlinks(policy);
+            manager.arrange_files().unwrap();
+
+            let arranged = test_dir.join("2020-06-21").join("04.jpg");
+            match policy {
+                SymlinkPolicy::Skip => {
+                    assert_that(&arranged).does_not_exist();
+                    assert_that(&outside.join("04.jpg")).exists();
+                }
+                SymlinkPolicy::Follow => {
+                    assert_that(&arranged).exists();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use photoman::{FailurePolicy, Manager};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite;
+
+#[test]
+pub fn plan_and_apply() {
+    let r = prepare_suite("plan_and_apply");
+
+    if let Ok(test_dir) = r {
+        let plan_file = test_dir.with_extension("plan.json");
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.plan(&plan_file).unwrap();
+
+        // nothing is changed by planning
+        assert_that(&plan_file).exists();
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+
+        let plan = fs::read_to_string(&plan_file).unwrap();
+        assert!(plan.contains(r#""op": "mkdir""#));
+        assert!(plan.contains("02.JPG exists\"")); // IMGP2011/02.JPG is skipped
+
+        Manager::new().apply(&plan_file).unwrap();
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2020-06-21").join("raw").join("01.raw")).exists();
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("03.JPG")).exists();
+        assert_that(&test_dir.join("IMGP1011")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn changed_sources_are_skipped() {
+    let r = prepare_suite("changed_sources_are_skipped");
+
+    if let Ok(test_dir) = r {
+        let plan_file = test_dir.with_extension("plan.json");
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.plan(&plan_file).unwrap();
+
+        let changed = test_dir.join("IMGP2011").join("03.JPG");
+        let file = fs::File::options().write(true).open(&changed).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+
+        Manager::new().apply(&plan_file).unwrap();
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&changed).exists();
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("03.JPG")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn csv_plan_and_apply() {
+    let r = prepare_suite("csv_plan_and_apply");
+
+    if let Ok(test_dir) = r {
+        let plan_file = test_dir.with_extension("plan.csv");
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.plan(&plan_file).unwrap();
+
+        let plan = fs::read_to_string(&plan_file).unwrap();
+        assert!(plan.starts_with("op,path,target,mode,overwrite,size,modified,reason\nwork_dir,"));
+        assert!(plan.contains("\nmkdir,"));
+
+        Manager::new().apply(&plan_file).unwrap();
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2022-10-02 (Pushkin)").join("03.JPG")).exists();
+        assert_that(&test_dir.join("IMGP1011")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn changed_sources_abort_apply() {
+    let r = prepare_suite("changed_sources_abort_apply");
+
+    if let Ok(test_dir) = r {
+        let plan_file = test_dir.with_extension("plan.json");
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.plan(&plan_file).unwrap();
+
+        let changed = test_dir.join("IMGP2011").join("03.JPG");
+        let file = fs::File::options().write(true).open(&changed).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+
+        let report = Manager::new()
+            .failure_policy(FailurePolicy::AbortBeforeExecute)
+            .apply(&plan_file)
+            .unwrap();
+        assert!(report.stopped);
+        assert_eq!(report.failures.len(), 1);
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn edited_plan() {
+    let r = prepare_suite("edited_plan");
+
+    if let Ok(test_dir) = r {
+        let plan_file = test_dir.with_extension("plan.json");
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.plan(&plan_file).unwrap();
+
+        // the step of 01.raw is removed
+        let mut plan: serde_json::Value = serde_json::from_str(&fs::read_to_string(&plan_file).unwrap()).unwrap();
+        let steps = plan["steps"].as_array_mut().unwrap();
+        steps.retain(|step| !step["from"].as_str().is_some_and(|from| from.ends_with("01.raw")));
+        fs::write(&plan_file, serde_json::to_string_pretty(&plan).unwrap()).unwrap();
+
+        Manager::new().apply(&plan_file).unwrap();
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&test_dir.join("IMGP1011").join("01.raw")).exists();
+
+        // the applied plan is undone by the journal
+        Manager::new().work_dir(&test_dir).undo().unwrap();
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+    }
+}
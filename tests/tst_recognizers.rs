@@ -0,0 +1,59 @@
+use photoman::{Layout, Manager};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite_from;
+
+#[test]
+pub fn recognized_folders() {
+    let r = prepare_suite_from("test_data/suite-recognizers", "recognized_folders");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        assert_that(&test_dir.join("2020").join("06").join("21 Peterhof").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2022-09-30..2022-10-05 Trip").join("03.JPG")).exists();
+        assert_that(&test_dir.join("20210715_Sochi").join("08.heic")).exists();
+        assert_that(&test_dir.join("2021.08.01").join("09.avif")).exists();
+
+        // files of recognized folders are not arranged
+        assert_that(&test_dir.join("2022-09-30..2022-10-05 Trip").join("02.JPG")).exists();
+        assert_that(&test_dir.join("2022-10-02")).does_not_exist();
+        assert_that(&test_dir.join("DCIM")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn recognized_folders_in_other_layout() {
+    let r = prepare_suite_from("test_data/suite-recognizers", "recognized_folders_in_other_layout");
+
+    if let Ok(test_dir) = r {
+        let layout: Layout = "{year}/{date} {event}".parse().unwrap();
+        let mut manager = Manager::new().work_dir(&test_dir).layout(layout);
+        manager.arrange_files().unwrap();
+
+        assert_that(&test_dir.join("2020").join("06").join("21 Peterhof").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2022-09-30..2022-10-05 Trip").join("03.JPG")).exists();
+        assert_that(&test_dir.join("20210715_Sochi").join("08.heic")).exists();
+    }
+}
+
+#[test]
+pub fn without_recognizers() {
+    let r = prepare_suite_from("test_data/suite-recognizers", "without_recognizers");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir).folder_recognizers(Vec::new());
+        manager.arrange_files().unwrap();
+
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2021-07-15").join("08.heic")).exists();
+        assert_that(&test_dir.join("2022-10-02").join("03.JPG")).exists();
+
+        // the range is matched by the template as a folder of its first day
+        assert_that(&test_dir.join("2022-09-30..2022-10-05 Trip").join("02.JPG")).exists();
+    }
+}
@@ -0,0 +1,44 @@
+use photoman::{Manager, RenameTemplate};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::{prepare_suite, prepare_suite_from};
+
+#[test]
+pub fn rename_shot_with_sidecars() {
+    let r = prepare_suite_from("test_data/suite-sidecars", "rename_shot_with_sidecars");
+
+    if let Ok(test_dir) = r {
+        let template: RenameTemplate = "{datetime}_{camera}_{seq}.{ext}".parse().unwrap();
+        let mut manager = Manager::new().work_dir(&test_dir).rename(template);
+        manager.arrange_files().unwrap();
+
+        // RAW+JPEG keep the same name, sidecars are renamed with their files
+        let dir_2020_06_21 = test_dir.join("2020-06-21");
+        assert_that(&dir_2020_06_21.join("20200621_222005_DSC-RX100M7_1.jpg")).exists();
+        assert_that(&dir_2020_06_21.join("20200621_222005_DSC-RX100M7_1.jpg.xmp")).exists();
+        assert_that(&dir_2020_06_21.join("raw").join("20200621_222005_DSC-RX100M7_1.raw")).exists();
+        assert_that(&dir_2020_06_21.join("raw").join("20200621_222005_DSC-RX100M7_1.xmp")).exists();
+        assert_that(&test_dir.join("IMGP4011")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn rename_same_names() {
+    let r = prepare_suite("rename_same_names");
+
+    if let Ok(test_dir) = r {
+        let template: RenameTemplate = "{name}_{seq}.{ext}".parse().unwrap();
+        let mut manager = Manager::new().work_dir(&test_dir).rename(template);
+        manager.arrange_files().unwrap();
+
+        // 02.JPG is not skipped, the folder already has a file with its name
+        let dir_2022_10_02 = test_dir.join("2022-10-02 (Pushkin)");
+        assert_that(&dir_2022_10_02.join("02.JPG")).exists();
+        assert_that(&dir_2022_10_02.join("02_1.JPG")).exists();
+        assert_that(&dir_2022_10_02.join("03_1.JPG")).exists();
+        assert_that(&test_dir.join("IMGP2011")).does_not_exist();
+    }
+}
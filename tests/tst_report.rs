@@ -0,0 +1,69 @@
+use std::fs;
+
+use photoman::{DateSource, Error, Manager, Operation, Resolution};
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite;
+
+#[test]
+pub fn report_of_arrangement() {
+    let r = prepare_suite("report_of_arrangement");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        let report = manager.arrange_files().unwrap();
+
+        let mut transferred: Vec<_> = report.transferred.iter().map(|t| t.to.clone()).collect();
+        transferred.sort();
+        let dir_2020_06_21 = test_dir.join("2020-06-21");
+        assert_eq!(
+            transferred,
+            vec![
+                dir_2020_06_21.join("01.jpg"),
+                dir_2020_06_21.join("raw").join("01.raw"),
+                test_dir.join("2022-10-02 (Pushkin)").join("03.JPG"),
+            ]
+        );
+        assert_eq!(report.created_folders, vec![dir_2020_06_21.clone(), dir_2020_06_21.join("raw")]);
+        assert_eq!(report.removed_folders, vec![test_dir.join("IMGP1011")]);
+
+        // IMGP2011/02.JPG exists in its folder
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].path, test_dir.join("IMGP2011").join("02.JPG"));
+        assert_eq!(report.conflicts[0].existing, report.conflicts[0].target);
+        assert_eq!(report.conflicts_resolved(Resolution::Skipped), 1);
+
+        assert_eq!(report.ignored, vec![test_dir.join("somefile")]);
+        assert!(!report.has_failures());
+        assert!(report.timings.total() >= report.timings.transfer);
+    }
+}
+
+#[test]
+pub fn report_of_unreadable_files() {
+    let r = prepare_suite("report_of_unreadable_files");
+
+    if let Ok(test_dir) = r {
+        let broken = test_dir.join("IMGP2011").join("04.JPG");
+        // JPEG header without EXIF
+        fs::write(&broken, [0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F', b'I', b'F', 0]).unwrap();
+
+        // without the modification time the file has no date
+        let mut manager = Manager::new()
+            .work_dir(&test_dir)
+            .date_sources(vec![DateSource::DateTimeOriginal, DateSource::FileName]);
+        let report = manager.arrange_files().unwrap();
+
+        // the file is left in place, others are arranged
+        assert!(report.has_failures());
+        assert_eq!(report.unreadable.len(), 1);
+        assert_eq!(report.unreadable[0].path, broken);
+        assert!(matches!(
+            &report.unreadable[0].error,
+            Error::Context { operation: Operation::ReadMetadata, path, .. } if *path == broken
+        ));
+        assert!(report.failures.is_empty());
+        assert_eq!(report.transferred.len(), 3);
+    }
+}
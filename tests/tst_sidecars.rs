@@ -0,0 +1,28 @@
+use photoman::Manager;
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite_from;
+
+#[test]
+pub fn sidecars_travel_with_files() {
+    let r = prepare_suite_from("test_data/suite-sidecars", "sidecars_travel_with_files");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        let dir_2020_06_21 = test_dir.join("2020-06-21");
+        let dir_2020_06_21_raw = dir_2020_06_21.join("raw");
+
+        // sidecar by stem belongs to the RAW, sidecar by full name - to its file
+        assert_that(&dir_2020_06_21_raw.join("01.raw")).exists();
+        assert_that(&dir_2020_06_21_raw.join("01.xmp")).exists();
+        assert_that(&dir_2020_06_21.join("01.jpg")).exists();
+        assert_that(&dir_2020_06_21.join("01.jpg.xmp")).exists();
+
+        assert_that(&test_dir.join("IMGP4011")).does_not_exist();
+    }
+}
@@ -0,0 +1,24 @@
+use photoman::Manager;
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite_from;
+
+#[test]
+pub fn detect_by_content() {
+    let r = prepare_suite_from("test_data/suite-other", "detect_by_content");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        // JPEG without extension is arranged, other files are ignored and left in place
+        let source_dir = test_dir.join("IMGP3011");
+
+        assert_that(&test_dir.join("2020-06-21").join("IMG0001")).exists();
+        assert_that(&source_dir.join("notes.txt")).exists();
+        assert_that(&source_dir.join("Thumbs.db")).exists();
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs;
+
+use photoman::{Manager, TransferMode};
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::{prepare_suite, prepare_suite_from};
+
+#[test]
+pub fn undo_arrangement() {
+    let r = prepare_suite("undo_arrangement");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+
+        let report = manager.undo().unwrap();
+        assert!(report.commands > 0);
+        assert!(!report.has_failures());
+
+        // files are moved back, the created folder is removed, the removed one is created
+        let dir_2022_10_02 = test_dir.join("2022-10-02 (Pushkin)");
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+        assert_that(&test_dir.join("IMGP1011").join("01.raw")).exists();
+        assert_that(&test_dir.join("IMGP2011").join("03.JPG")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+        assert_that(&dir_2022_10_02.join("03.JPG")).does_not_exist();
+        assert_that(&dir_2022_10_02.join("02.JPG")).exists();
+
+        // the run is undone, nothing to undo
+        assert_eq!(manager.undo().unwrap().commands, 0);
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+    }
+}
+
+#[test]
+pub fn undo_copies() {
+    let library = prepare_suite("undo_copies_library");
+    let card = prepare_suite_from("test_data/suite-nested", "undo_copies_card");
+
+    if let (Ok(library), Ok(card)) = (library, card) {
+        let mut manager = Manager::new()
+            .work_dir(&library)
+            .import_from(vec![card.clone()])
+            .transfer_mode(TransferMode::Copy);
+        manager.arrange_files().unwrap();
+        assert_that(&library.join("2020-06-21").join("01.jpg")).exists();
+
+        manager.undo().unwrap();
+
+        // copies are removed, the card is kept as is
+        assert_that(&library.join("2020-06-21")).does_not_exist();
+        assert_that(&library.join("2022-10-02 (Pushkin)").join("03.JPG")).does_not_exist();
+        assert_that(&library.join("2022-10-02 (Pushkin)").join("02.JPG")).exists();
+        assert_that(&card.join("01.jpg")).exists();
+    }
+}
+
+#[test]
+pub fn undo_dry_run() {
+    let r = prepare_suite("undo_dry_run");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        Manager::new().work_dir(&test_dir).dry_run().undo().unwrap();
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+
+        // the run isn't marked as undone by the dry run
+        manager.undo().unwrap();
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+    }
+}
+
+#[test]
+pub fn undo_after_run_without_commands() {
+    let r = prepare_suite("undo_after_run_without_commands");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+        // everything is arranged, the second run has nothing to do
+        manager.arrange_files().unwrap();
+
+        manager.undo().unwrap();
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn undo_is_repeated_after_failures() {
+    let r = prepare_suite("undo_is_repeated_after_failures");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        // the new file takes the place of the arranged one, it can't be moved back
+        let blocking = test_dir.join("IMGP1011").join("01.jpg");
+        fs::create_dir(test_dir.join("IMGP1011")).unwrap();
+        fs::write(&blocking, "new image").unwrap();
+        let report = manager.undo().unwrap();
+        assert!(report.has_failures());
+        assert_that(&test_dir.join("2020-06-21").join("01.jpg")).exists();
+        assert_that(&test_dir.join("IMGP1011").join("01.raw")).exists();
+
+        // the run isn't marked as undone, reverted commands are skipped
+        fs::remove_file(&blocking).unwrap();
+        let report = manager.undo().unwrap();
+        assert!(!report.has_failures());
+        assert_that(&test_dir.join("IMGP1011").join("01.jpg")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+        assert_eq!(manager.undo().unwrap().commands, 0);
+    }
+}
+
+#[test]
+pub fn undo_keeps_edited_copies() {
+    let library = prepare_suite("undo_keeps_edited_copies_library");
+    let card = prepare_suite_from("test_data/suite-nested", "undo_keeps_edited_copies_card");
+
+    if let (Ok(library), Ok(card)) = (library, card) {
+        let mut manager = Manager::new()
+            .work_dir(&library)
+            .import_from(vec![card.clone()])
+            .transfer_mode(TransferMode::Copy);
+        manager.arrange_files().unwrap();
+
+        // the copy is edited in the library, it and its folder aren't removed
+        let edited = library.join("2020-06-21").join("01.jpg");
+        fs::write(&edited, "edited image").unwrap();
+        let report = manager.undo().unwrap();
+        let failed: Vec<_> = report.failures.iter().map(|failure| failure.path.clone()).collect();
+        assert_eq!(failed, vec![edited.clone(), library.join("2020-06-21")]);
+        assert_eq!(fs::read(&edited).unwrap(), b"edited image");
+        assert_that(&library.join("2022-10-02 (Pushkin)").join("03.JPG")).does_not_exist();
+        assert_that(&card.join("01.jpg")).exists();
+    }
+}
@@ -0,0 +1,43 @@
+use photoman::Manager;
+use spectral::assert_that;
+use spectral::prelude::PathAssertions;
+
+mod prepare_suite;
+
+use prepare_suite::prepare_suite_from;
+
+#[test]
+pub fn arrange_videos() {
+    let r = prepare_suite_from("test_data/suite-video", "arrange_videos");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir);
+        manager.arrange_files().unwrap();
+
+        // all videos are taken on 21 june 2020
+        let dir_2020_06_21 = test_dir.join("2020-06-21 (Peterhof)");
+
+        assert_that(&dir_2020_06_21.join("05.mov")).exists();
+        assert_that(&dir_2020_06_21.join("06.avi")).exists();
+        assert_that(&dir_2020_06_21.join("07.mts")).exists();
+        assert_that(&test_dir.join("DCIM")).does_not_exist();
+    }
+}
+
+#[test]
+pub fn separate_video() {
+    let r = prepare_suite_from("test_data/suite-video", "separate_video");
+
+    if let Ok(test_dir) = r {
+        let mut manager = Manager::new().work_dir(&test_dir).separate_video();
+        manager.arrange_files().unwrap();
+
+        // the video folder is created in the existing folder of the date
+        let dir_video = test_dir.join("2020-06-21 (Peterhof)").join("video");
+
+        assert_that(&dir_video.join("05.mov")).exists();
+        assert_that(&dir_video.join("06.avi")).exists();
+        assert_that(&dir_video.join("07.mts")).exists();
+        assert_that(&test_dir.join("2020-06-21")).does_not_exist();
+    }
+}